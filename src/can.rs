@@ -1,16 +1,24 @@
 use macroquad::prelude::*;
-use super::{Circle, ArenaKey};
+use super::{Collider, ArenaKey, Layers, Material, Shape, Slots};
 
 #[derive(Clone)]
 pub struct Can {
     pub pos: Vec2,
     pub vel: Vec2,
+    pub material: Material,
 }
 impl Can {
+    pub const MATERIAL: Material = Material {
+        mass: 1.0,
+        restitution: 0.5,
+        friction: 0.3,
+    };
+
     pub fn new(pos: Vec2) -> Self {
         Self {
             pos,
             vel: Vec2::zero(),
+            material: Self::MATERIAL,
         }
     }
 
@@ -21,8 +29,9 @@ impl Can {
         draw_circle(x + 0.44, y, 0.5, BROWN);
     }
 
+    /// Friction is applied once per simulation step.
     pub fn slide(&mut self, friction: f32) {
-        let Self { pos, vel } = self;
+        let Self { pos, vel, .. } = self;
         *vel *= friction;
         *pos += *vel;
     }
@@ -30,26 +39,33 @@ impl Can {
     pub fn knockback(&mut self, normal: Vec2) {
         self.vel += normal;
     }
+
+    pub fn bump(&mut self, impulse: Vec2, push: Vec2) {
+        self.vel += impulse;
+        self.pos += push;
+    }
 }
 
-/// Thin wrapper around a Vec of Cans.
+/// Thin wrapper around the Slots the Cans are kept in.
 /// Abstracts away the fact that in order to preserve the 3d illusion,
 /// cans need to be sorted by their x position before being rendered.
+#[derive(Clone)]
 pub struct Cantainer {
-    cans: Vec<Can>,
+    cans: Slots<Can>,
     temp: Vec<Can>,
 }
 impl Cantainer {
     pub fn new(cans: Vec<Can>) -> Self {
         Self {
             temp: cans.clone(),
-            cans,
+            cans: cans.into_iter().collect(),
         }
     }
 
     pub fn draw(&mut self) {
         use std::cmp::Ordering;
-        self.temp = self.cans.clone();
+        self.temp.clear();
+        self.temp.extend(self.cans.values().cloned());
         self.temp.sort_by(|a, b| {
             b.pos.x()
                 .partial_cmp(&a.pos.x())
@@ -58,16 +74,29 @@ impl Cantainer {
         for can in &self.temp { can.draw() };
     }
 
-    pub fn circles(&self) -> impl Iterator<Item = Circle> + '_ {
-        self.cans.iter().enumerate().map(|(i, c)| Circle {
-            pos: c.pos,
-            radius: 0.5,
-            key: ArenaKey::Can(i),
+    /// The cans as they were `t` of the way from `prev` to `self`, for rendering between steps.
+    /// Cans that weren't around in `prev` are left where they are.
+    pub fn interpolate(&self, prev: &Cantainer, t: f32) -> Cantainer {
+        let mut cans = self.clone();
+        for (handle, can) in cans.cans.iter_mut() {
+            if let Some(then) = prev.cans.get(handle) {
+                can.pos = then.pos.lerp(can.pos, t);
+            }
+        }
+        cans
+    }
+
+    pub fn colliders(&self) -> impl Iterator<Item = Collider> + '_ {
+        self.cans.iter().map(|(handle, c)| Collider {
+            shape: Shape::Circle { pos: c.pos, radius: 0.5 },
+            key: ArenaKey::Can(handle),
+            layer: Layers::CAN | Layers::GRABBABLE,
+            mask: Layers::ALL,
         })
     }
 }
 impl std::ops::Deref for Cantainer {
-    type Target = Vec<Can>;
+    type Target = Slots<Can>;
 
     fn deref(&self) -> &Self::Target {
         &self.cans
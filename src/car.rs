@@ -1,5 +1,5 @@
 use macroquad::prelude::*;
-use super::{Circle, math::*};
+use super::{Collider, Input, Layers, Material, Shape, SimClock, Surface, math::*};
 
 fn smoothstep(x: f32) -> f32 {
     if x < 0.0 {
@@ -23,8 +23,8 @@ pub enum ThrottleSlide {
     Nah
 }
 
+#[derive(Clone)]
 pub struct Car {
-    pub tex: Texture2D,
     pub pos: Vec2,
     pub dir: Vec2,
     pub speed: f32,
@@ -32,11 +32,14 @@ pub struct Car {
     pub throttle_slide: ThrottleSlide,
 }
 impl Car {
-    pub fn new(tex: Texture2D) -> Self {
-        set_texture_filter(tex, FilterMode::Nearest);
+    pub const MATERIAL: Material = Material {
+        mass: 4.0,
+        restitution: 0.2,
+        friction: 0.6,
+    };
 
+    pub fn new() -> Self {
         Self {
-            tex,
             pos: vec2(0.0, 0.0),
             dir: vec2(1.0, 0.0),
             vel: vec2(0.0, 0.0),
@@ -45,19 +48,47 @@ impl Car {
         }
     }
 
-    pub fn circles(&self) -> impl Iterator<Item = Circle> {
-        std::iter::once(Circle {
-            pos: self.pos + self.dir * 0.62,
-            radius: 0.515,
-            key: super::ArenaKey::Car,
-        }).chain(std::iter::once(Circle {
-            pos: self.pos - self.dir * 0.65,
-            radius: 0.515,
+    pub fn colliders(&self) -> impl Iterator<Item = Collider> {
+        std::iter::once(Collider {
+            shape: Shape::OrientedBox {
+                center: self.pos - self.dir * 0.015,
+                half_extents: vec2(1.15, 0.5),
+                angle: vec_to_angle(self.dir),
+            },
             key: super::ArenaKey::Car,
-        }))
-        .into_iter()
+            layer: Layers::CAR,
+            mask: Layers::ALL,
+        })
     }
-    
+
+
+    /// How far the car moves each step.
+    pub fn velocity(&self) -> Vec2 {
+        self.vel * self.speed
+    }
+
+    /// Knocks the car by `impulse`, a change in velocity, and `push`, a change in position.
+    /// Anything taking away from its speed knocks it out of line with where it's pointing,
+    /// which costs it speed until it straightens back out.
+    pub fn bump(&mut self, impulse: Vec2, push: Vec2) {
+        let vel = self.velocity() + impulse;
+        self.speed = vel.length();
+        if self.speed > 0.0 {
+            self.vel = vel / self.speed;
+        }
+        self.pos += push;
+    }
+
+    /// The car as it was `t` of the way from `prev` to `self`, for rendering between steps.
+    pub fn interpolate(&self, prev: &Car, t: f32) -> Car {
+        Car {
+            pos: prev.pos.lerp(self.pos, t),
+            dir: slerp(prev.dir, self.dir, t),
+            vel: slerp(prev.vel, self.vel, t),
+            ..self.clone()
+        }
+    }
+
     pub fn angle(&self) -> f32 {
         -(vec_to_angle(self.vel).to_degrees() + 90.0)
     }
@@ -67,30 +98,31 @@ impl Car {
         self.pos - self.dir * 0.44
     }
 
-    pub fn controls(&mut self, friction: f32) {
+    pub fn controls(&mut self, input: &Input, clock: &SimClock, surface: &Surface) {
         use std::f32::consts::PI;
         const MAX_SPEED: f32 = 0.175;
         let Self { speed, throttle_slide, dir, pos, vel, .. } = self;
         let angle = vec_to_angle(*dir);
+        let now = clock.now();
 
-        *throttle_slide = match (is_key_down(KeyCode::W), *throttle_slide) {
+        *throttle_slide = match (input.throttle, *throttle_slide) {
             (true, ThrottleSlide::Nah) => ThrottleSlide::Forward {
-                start: get_time()
+                start: now
             },
             (true, ThrottleSlide::Back { start, forward_time }) => ThrottleSlide::Forward {
-                start: get_time() - (forward_time - (get_time() - start)).max(0.0),
+                start: now - (forward_time - (now - start)).max(0.0),
             },
             (false, ThrottleSlide::Forward { start }) => ThrottleSlide::Back {
-                start: get_time(),
-                forward_time: (get_time() - start).min(4.0),
+                start: now,
+                forward_time: (now - start).min(4.0),
             },
             (_, o) => o,
         };
 
         let throttle = {
             let t = match *throttle_slide {
-                ThrottleSlide::Forward { start } => (get_time() - start) as f32,
-                ThrottleSlide::Back { forward_time, start } => (forward_time - (get_time() - start) * 2.0).max(0.0) as f32,
+                ThrottleSlide::Forward { start } => (now - start) as f32,
+                ThrottleSlide::Back { forward_time, start } => (forward_time - (now - start) * 2.0).max(0.0) as f32,
                 ThrottleSlide::Nah => 0.0
             };
 
@@ -108,8 +140,14 @@ impl Car {
             }
         };
 
-        *speed = MAX_SPEED * throttle * ((vel.dot(*dir) - 0.87).max(0.0) / 0.1);
-        *vel += *dir * 0.1 * throttle;
+        // speed builds up towards what the throttle's asking for, and falls away from it as quickly as the ground drags on it,
+        // so whatever knocks the car about carries it along for a bit
+        const ACCEL: f32 = 0.01;
+        const DECEL: f32 = 0.002;
+        let target = MAX_SPEED * throttle * ((vel.dot(*dir) - 0.87).max(0.0) / 0.1) * (1.0 - surface.drag);
+        let decel = DECEL + *speed * surface.drag.max(0.0);
+        *speed += (target - *speed).clamp(-decel, ACCEL);
+        *vel += *dir * 0.1 * throttle * surface.grip;
 
         *vel = if vel.length_squared() != 0.0 {
             vel.normalize()
@@ -117,28 +155,28 @@ impl Car {
             *vel
         };
             
-        let (l, r) = (is_key_down(KeyCode::D), is_key_down(KeyCode::A));
-        if l ^ r {
+        let steer = input.steer.clamp(-1.0, 1.0);
+        if steer != 0.0 {
             *dir = angle_to_vec(
                 angle + PI/216.0
                     * (*speed / MAX_SPEED).min(1.0)
-                    * if r { -1.0 } else { 1.0 },
+                    * steer
+                    * surface.steer,
             );
         }
 
-        *speed *= friction;
         *pos += *vel * *speed;
     }
 
-    pub fn draw(&self) {
-        let &Self { tex, pos, dir, .. } = self;
+    pub fn draw(&self, tex: Texture2D, color: Color) {
+        let &Self { pos, dir, .. } = self;
         let tex_size = vec2(tex.width(), tex.height()) * 0.125;
         let (x, y) = (pos - tex_size / 2.0).into();
         draw_texture_ex(
             tex,
             x,
             y,
-            WHITE,
+            color,
             DrawTextureParams {
                 rotation: vec_to_angle(dir) + std::f32::consts::FRAC_PI_2,
                 dest_size: Some(tex_size),
@@ -0,0 +1,205 @@
+use macroquad::prelude::*;
+use super::{Hook, math::*};
+
+/// How many pieces the chain is simulated in, however long it's let out.
+const SEGMENTS: usize = 16;
+/// How many times a step the links are pulled back together. More is stiffer, and slower.
+const ITERATIONS: usize = 12;
+/// How much of its speed a link keeps each step, dragging along the ground.
+const LINK_DRAG: f32 = 0.9;
+/// How much heavier the hook end is than a link, so how much less the links can shove it around.
+const END_WEIGHT: f32 = 4.0;
+
+/// The chain between the car's dock and the hook, simulated as a string of Verlet particles
+/// kept from drifting further apart than their share of the chain's length.
+/// Links can bunch up when there's slack, but barely stretch, so a taut chain drags its end along.
+/// Wherever it's wrapped around corners it's pulled tight, so only the stretch past the last corner is simulated.
+#[derive(Clone)]
+pub struct Chain {
+    /// From the dock through every corner the chain's wrapped around, ending where the simulated stretch starts.
+    around: Vec<Vec2>,
+    /// From the last of `around` out to the hook.
+    points: Vec<Vec2>,
+    /// Where each point was the step before, which is all Verlet needs to know how it's moving.
+    prev: Vec<Vec2>,
+}
+impl Chain {
+    pub fn new(dock: Vec2) -> Self {
+        Self {
+            around: vec![dock],
+            points: vec![dock; SEGMENTS + 1],
+            prev: vec![dock; SEGMENTS + 1],
+        }
+    }
+
+    /// Where the chain was tied to the car as of its latest step.
+    pub fn dock(&self) -> Vec2 {
+        self.around[0]
+    }
+
+    /// Lays the chain out still and straight from `start` to `end`, unwrapped from everything.
+    pub fn lay(&mut self, start: Vec2, end: Vec2) {
+        self.around = vec![start];
+        self.lay_free(start, end);
+    }
+
+    fn lay_free(&mut self, start: Vec2, end: Vec2) {
+        for (i, (point, prev)) in self.points.iter_mut().zip(&mut self.prev).enumerate() {
+            *point = start.lerp(end, i as f32 / SEGMENTS as f32);
+            *prev = *point;
+        }
+    }
+
+    /// Keeps the chain strung between the dock and wherever the hook has gone,
+    /// or coiled up at the dock while it's stowed. Hooks holding onto something move the chain themselves.
+    pub fn follow(&mut self, dock: Vec2, hook: &Hook) {
+        match *hook {
+            Hook::Ready { .. } => self.lay(dock, dock),
+            Hook::Launched { pos, .. } | Hook::Retracting { pos, .. } => {
+                self.step(&[dock], pos, (pos - dock).length(), false);
+            }
+            Hook::Locked { .. } | Hook::Anchored { .. } => {},
+        }
+    }
+
+    /// Moves every link along for a step, then pulls them back within `length` of each other all told.
+    /// The chain runs tight from the dock through `around`, and the links are free from its last point on.
+    /// A `free` end is pulled around by the links like any of them, only less for being heavier,
+    /// and where it ends up is returned; otherwise it's pinned to `end`.
+    pub fn step(&mut self, around: &[Vec2], end: Vec2, length: f32, free: bool) -> Vec2 {
+        let start = around[around.len() - 1];
+        // caught on or let go of a corner, so the free stretch starts somewhere else entirely
+        if self.around.len() != around.len() {
+            self.lay_free(start, self.points[SEGMENTS]);
+        }
+        self.around = around.to_vec();
+        let Self { points, prev, .. } = self;
+        for (point, prev) in points.iter_mut().zip(prev.iter_mut()).take(SEGMENTS).skip(1) {
+            let vel = (*point - *prev) * LINK_DRAG;
+            *prev = *point;
+            *point += vel;
+        }
+        prev[0] = points[0];
+        prev[SEGMENTS] = points[SEGMENTS];
+        points[0] = start;
+        points[SEGMENTS] = end;
+
+        let rest = length / SEGMENTS as f32;
+        let weight = |i: usize| match i {
+            0 => 0.0,
+            SEGMENTS if free => 1.0 / END_WEIGHT,
+            SEGMENTS => 0.0,
+            _ => 1.0,
+        };
+        for _ in 0..ITERATIONS {
+            for i in 0..SEGMENTS {
+                let delta = points[i + 1] - points[i];
+                let distance = delta.length();
+                let (a, b) = (weight(i), weight(i + 1));
+                if distance <= rest || a + b == 0.0 {
+                    continue;
+                }
+                let fix = delta * (distance - rest) / distance / (a + b);
+                points[i] += fix * a;
+                points[i + 1] -= fix * b;
+            }
+        }
+
+        // however far behind the iterations leave it, nothing ends up further from the start than the chain reaches
+        let last = if free { SEGMENTS } else { SEGMENTS - 1 };
+        for (i, point) in points.iter_mut().enumerate().take(last + 1).skip(1) {
+            let delta = *point - start;
+            let reach = rest * i as f32;
+            if delta.length() > reach {
+                *point = start + delta.normalize() * reach;
+            }
+        }
+        points[SEGMENTS]
+    }
+
+    /// The chain as it was `t` of the way from `prev` to `self`, for rendering between steps.
+    pub fn interpolate(&self, prev: &Chain, t: f32) -> Chain {
+        let mut chain = self.clone();
+        for (point, then) in chain.points.iter_mut().zip(&prev.points) {
+            *point = then.lerp(*point, t);
+        }
+        if chain.around.len() == prev.around.len() {
+            for (point, then) in chain.around.iter_mut().zip(&prev.around) {
+                *point = then.lerp(*point, t);
+            }
+        }
+        chain
+    }
+
+    /// Every point the chain runs through, from the dock out to the hook.
+    fn path(&self) -> Vec<Vec2> {
+        self.around[..self.around.len() - 1].iter().chain(&self.points).copied().collect()
+    }
+
+    pub fn draw(&self, color: Color) {
+        const LINK_LENGTH: f32 = 0.35;
+        const LINK_WIDTH: f32 = 0.12;
+        const LINK_OVERLAP: f32 = 0.12;
+        let path = self.path();
+        let length: f32 = path.windows(2).map(|pair| (pair[1] - pair[0]).length()).sum();
+        let link_count = length / LINK_LENGTH;
+        // the point `distance` along the chain, going from the hook back towards the dock
+        let along = |mut distance: f32| {
+            for pair in path.windows(2).rev() {
+                let (near, far) = (pair[1], pair[0]);
+                let length = (far - near).length();
+                if distance <= length && length > 0.0 {
+                    return near.lerp(far, distance / length);
+                }
+                distance -= length;
+            }
+            path[0]
+        };
+
+        let line = |s: Vec2, e: Vec2| {
+            let (x, y) = s.into();
+            let (w, z) = e.into();
+            draw_line(x, y, w, z, 0.07, color);
+        };
+
+        for link in 0..link_count as usize {
+            let start_middle = along((link as f32 - LINK_OVERLAP).max(0.0) * LINK_LENGTH);
+            let end_middle = along(((link + 1) as f32 + LINK_OVERLAP) * LINK_LENGTH);
+            if link % 2 == 0 {
+                line(start_middle, end_middle);
+            } else {
+                let out = perp_vec(end_middle - start_middle).normalize() * LINK_WIDTH;
+                line(start_middle + out, start_middle - out);
+                line(end_middle + out, end_middle - out);
+                line(start_middle + out, end_middle + out);
+                line(start_middle - out, end_middle - out);
+            }
+        }
+
+        line(along(link_count.floor() * LINK_LENGTH), path[0]);
+    }
+}
+
+#[test]
+fn taut_chains_drag_their_end_along() {
+    let mut chain = Chain::new(Vec2::zero());
+    chain.lay(Vec2::zero(), vec2(4.0, 0.0));
+    let mut end = vec2(4.0, 0.0);
+    // the dock backs off, and then comes back, leaving the chain slack
+    for step in 0..60 {
+        let start = vec2(if step < 30 { -0.1 * step as f32 } else { -3.0 }, 0.0);
+        end = chain.step(&[start], end, 4.0, true);
+        assert!((end - start).length() <= 4.0 + 1e-4);
+        for pair in chain.points.windows(2) {
+            assert!((pair[1] - pair[0]).length() <= 0.25 * 1.1);
+        }
+    }
+    assert!(end.x() < 1.5, "end should have been dragged along, but is at {:?}", end);
+
+    // with a shorter pull, the links gather up rather than pushing the end away
+    let before = end;
+    for _ in 0..30 {
+        end = chain.step(&[vec2(-2.0, 0.0)], end, 4.0, true);
+    }
+    assert!((end - before).length() < 0.1);
+}
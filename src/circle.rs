@@ -1,60 +1,513 @@
 use macroquad::prelude::Vec2;
+use std::collections::{BTreeSet, HashMap};
+use super::{Handle, shape::{self, Shape}};
 
 #[derive(Copy, Clone)]
-pub struct Circle {
-    pub pos: Vec2,
-    pub radius: f32,
+pub struct Collider {
+    pub shape: Shape,
     pub key: ArenaKey,
+    /// What kind of thing this collider is.
+    pub layer: Layers,
+    /// What kinds of things this collider wants to collide with.
+    pub mask: Layers,
+}
+impl Collider {
+    /// Two colliders only collide if each is on a layer the other's mask includes.
+    fn interacts(&self, other: &Collider) -> bool {
+        self.key != other.key
+            && self.layer.intersects(other.mask)
+            && other.layer.intersects(self.mask)
+    }
+}
+
+/// A set of collision layers, as bit flags.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Layers(u32);
+impl Layers {
+    pub const NONE: Layers = Layers(0);
+    pub const ALL: Layers = Layers(!0);
+    pub const CAR: Layers = Layers(1);
+    pub const CAN: Layers = Layers(1 << 1);
+    pub const HOOK: Layers = Layers(1 << 2);
+    /// Things the hook can latch onto.
+    pub const GRABBABLE: Layers = Layers(1 << 3);
+    /// Collisions with sensors are reported, but never pushed apart.
+    pub const SENSOR: Layers = Layers(1 << 4);
+    pub const WALL: Layers = Layers(1 << 5);
+
+    pub fn intersects(self, other: Layers) -> bool {
+        self.0 & other.0 != 0
+    }
 }
-#[derive(Copy, Clone, PartialEq)]
+impl std::ops::BitOr for Layers {
+    type Output = Layers;
+
+    fn bitor(self, other: Layers) -> Layers {
+        Layers(self.0 | other.0)
+    }
+}
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum ArenaKey {
     Hook,
     Car,
-    Can(usize),
+    Can(Handle),
+    /// One of the Map's trigger volumes.
+    Trigger(usize),
+    /// One of the Map's walls.
+    Wall(usize),
+    /// One of the Map's drop-off zones.
+    Zone(usize),
+    /// One of the Map's anchor posts.
+    Anchor(usize),
 }
+#[derive(Clone)]
 pub struct Collision {
     pub members: [ArenaKey; 2],
+    /// Points from the second member towards the first.
     pub normal: Vec2,
     pub depth: f32,
+    /// Whether either member is a sensor, in which case nothing should be pushed apart.
+    pub sensor: bool,
+}
+impl Collision {
+    /// The same collision as seen by each of its members,
+    /// so that whichever member is first has the normal pushing it away from the other.
+    pub fn both_ways(self) -> [Collision; 2] {
+        let Collision { members: [a, b], normal, depth, sensor } = self;
+        [
+            Collision { members: [a, b], normal, depth, sensor },
+            Collision { members: [b, a], normal: -normal, depth, sensor },
+        ]
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Overlap {
+    /// Started overlapping the trigger this step.
+    Enter,
+    /// Was already overlapping the trigger last step, and still is.
+    Stay,
+    /// Was overlapping the trigger last step, but isn't anymore.
+    Exit,
+}
+
+/// Something overlapping a sensor, or having stopped overlapping one.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TriggerEvent {
+    pub trigger: ArenaKey,
+    pub other: ArenaKey,
+    pub overlap: Overlap,
+}
+
+/// What something is made of, as far as bumping into other things goes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Material {
+    /// Use `f32::INFINITY` for things that can't be moved at all.
+    pub mass: f32,
+    /// How much of the speed two things meet at they bounce apart with, from 0 to 1.
+    pub restitution: f32,
+    /// How much two things resist sliding along each other, from 0 to 1.
+    pub friction: f32,
+}
+impl Material {
+    fn inverse_mass(&self) -> f32 {
+        self.mass.recip()
+    }
+}
+
+/// Something's velocity, per step, and what it's made of.
+#[derive(Copy, Clone)]
+pub struct Body {
+    pub vel: Vec2,
+    pub material: Material,
+}
+
+/// How much each member of a collision should change its velocity and position
+/// to bounce off of the other one.
+pub struct Resolution {
+    pub impulses: [Vec2; 2],
+    pub pushes: [Vec2; 2],
 }
 
+/// Works out an impulse-based response to `collision`, where `bodies` are in the same order as its members.
+/// Heavier bodies move less, and bodies already moving apart aren't sped up, only pushed out of each other.
+pub fn resolve(&Collision { normal, depth, .. }: &Collision, bodies: [Body; 2]) -> Option<Resolution> {
+    /// Overlap left alone so things resting against each other don't jitter.
+    const SLOP: f32 = 0.01;
+    /// How much of the overlap is undone each step.
+    const CORRECTION: f32 = 0.8;
+
+    let [a, b] = bodies;
+    let (inv_a, inv_b) = (a.material.inverse_mass(), b.material.inverse_mass());
+    let inv_sum = inv_a + inv_b;
+    if inv_sum == 0.0 {
+        return None;
+    }
+
+    let push = normal * (depth - SLOP).max(0.0) * CORRECTION / inv_sum;
+    let pushes = [push * inv_a, -push * inv_b];
+
+    let rel = a.vel - b.vel;
+    let closing = rel.dot(normal);
+    if closing >= 0.0 {
+        return Some(Resolution { impulses: [Vec2::zero(); 2], pushes });
+    }
+
+    let restitution = (a.material.restitution + b.material.restitution) / 2.0;
+    let j = -(1.0 + restitution) * closing / inv_sum;
+    let mut impulse = normal * j;
+
+    let slide = rel - normal * closing;
+    if slide.length_squared() > 0.0 {
+        let tangent = slide.normalize();
+        let friction = (a.material.friction * b.material.friction).sqrt();
+        let jt = (-rel.dot(tangent) / inv_sum).max(-friction * j);
+        impulse += tangent * jt;
+    }
+
+    Some(Resolution {
+        impulses: [impulse * inv_a, -impulse * inv_b],
+        pushes,
+    })
+}
+
+/// Side length of the squares the broadphase sorts colliders into.
+/// Colliders bigger than a cell just end up in several of them.
+const CELL: f32 = 2.0;
+
+/// The range of cells a collider overlaps, as `[min x, min y, max x, max y]`.
+fn cells(c: &Collider) -> [i32; 4] {
+    let cell = |f: f32| (f / CELL).floor() as i32;
+    let (min, max) = c.shape.bounds();
+    [cell(min.x()), cell(min.y()), cell(max.x()), cell(max.y())]
+}
+
+fn narrowphase(c0: &Collider, c1: &Collider) -> Option<Collision> {
+    shape::overlap(&c0.shape, &c1.shape).map(|(normal, depth)| Collision {
+        normal,
+        members: [c0.key, c1.key],
+        depth,
+        sensor: (c0.layer | c1.layer).intersects(Layers::SENSOR),
+    })
+}
+
+/// Where a cast first touched a collider.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RayHit {
+    pub key: ArenaKey,
+    pub layer: Layers,
+    /// How far along the cast the hit happened.
+    pub distance: f32,
+    /// Where the center of what was cast ended up when it hit.
+    pub pos: Vec2,
+    /// Which way the surface that was hit faces.
+    pub normal: Vec2,
+}
+
+/// Collides everything in the world against everything else.
+/// Named for when everything in it was a circle.
+#[derive(Clone)]
 pub struct CircleArena {
-    circles: Vec<Circle>,
+    colliders: Vec<Collider>,
     collided: Vec<Collision>,
+    spans: Vec<[i32; 4]>,
+    grid: HashMap<(i32, i32), Vec<usize>>,
+    pairs: Vec<(usize, usize)>,
+    /// Every `[sensor, other]` that overlapped as of the last call to `collide`.
+    overlapping: BTreeSet<[ArenaKey; 2]>,
+    triggered: Vec<TriggerEvent>,
 }
 impl CircleArena {
     pub fn new() -> Self {
         Self {
-            circles: Vec::with_capacity(1000),
+            colliders: Vec::with_capacity(1000),
             collided: Vec::with_capacity(1000),
+            spans: Vec::with_capacity(1000),
+            grid: HashMap::new(),
+            pairs: Vec::with_capacity(1000),
+            overlapping: BTreeSet::new(),
+            triggered: Vec::with_capacity(100),
         }
     }
 
-    pub fn collide(&mut self, new_circles: impl Iterator<Item = Circle>) {
-        let Self { circles, collided, .. } = self;
-        circles.clear();
-        circles.extend(new_circles);
+    /// Finds every pair of overlapping colliders, reporting each pair once.
+    /// Colliders sharing a key never collide with each other,
+    /// and neither do colliders whose layers and masks don't line up.
+    pub fn collide(&mut self, new_colliders: impl Iterator<Item = Collider>) {
+        let Self { colliders, collided, spans, grid, pairs, overlapping, triggered } = self;
+        colliders.clear();
+        colliders.extend(new_colliders);
+        spans.clear();
+        spans.extend(colliders.iter().map(cells));
 
-        collided.clear();
-        for c0 in &*circles {
-            for c1 in &*circles {
-                if c0.key != c1.key {
-                    let delta = c0.pos - c1.pos;
-                    let dist = delta.length();
-                    let depth = (c0.radius + c1.radius) - dist;
-                    if depth > 0.0 {
-                        collided.push(Collision {
-                            normal: delta.normalize(),
-                            members: [c0.key, c1.key],
-                            depth,
-                        });
+        // cells nothing was in last time are dropped, the rest keep their allocations
+        grid.retain(|_, bucket| !bucket.is_empty());
+        grid.values_mut().for_each(Vec::clear);
+        for (i, &[x0, y0, x1, y1]) in spans.iter().enumerate() {
+            if colliders[i].mask == Layers::NONE {
+                continue;
+            }
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    grid.entry((x, y)).or_default().push(i);
+                }
+            }
+        }
+
+        pairs.clear();
+        for (&cell, bucket) in &*grid {
+            for (n, &i) in bucket.iter().enumerate() {
+                for &j in &bucket[n + 1..] {
+                    let ([ix, iy, ..], [jx, jy, ..]) = (spans[i], spans[j]);
+                    // colliders sharing several cells are only paired in the first of them
+                    if (ix.max(jx), iy.max(jy)) == cell && colliders[i].interacts(&colliders[j]) {
+                        pairs.push((i.min(j), i.max(j)));
                     }
                 }
             }
         }
+        // the grid hands pairs out in no particular order,
+        // but resolving them in a consistent one keeps replays deterministic
+        pairs.sort_unstable();
+
+        collided.clear();
+        let mut now = BTreeSet::new();
+        for &(i, j) in &*pairs {
+            let (c0, c1) = (&colliders[i], &colliders[j]);
+            if let Some(collision) = narrowphase(c0, c1) {
+                if collision.sensor {
+                    now.insert(match c0.layer.intersects(Layers::SENSOR) {
+                        true => [c0.key, c1.key],
+                        false => [c1.key, c0.key],
+                    });
+                }
+                collided.push(collision);
+            }
+        }
+
+        triggered.clear();
+        for &[trigger, other] in now.union(overlapping) {
+            let overlap = match (overlapping.contains(&[trigger, other]), now.contains(&[trigger, other])) {
+                (false, true) => Overlap::Enter,
+                (true, true) => Overlap::Stay,
+                _ => Overlap::Exit,
+            };
+            triggered.push(TriggerEvent { trigger, other, overlap });
+        }
+        *overlapping = now;
+    }
+
+    /// Every collider on one of `layers` that a circle of `radius`, swept from `origin` along `dir` for `max`,
+    /// would touch if it kept going through everything, nearest first.
+    /// Only sees the colliders from the last call to `collide`. A `radius` of zero casts a plain ray.
+    pub fn cast_all(&self, origin: Vec2, dir: Vec2, max: f32, radius: f32, layers: Layers) -> Vec<RayHit> {
+        let end = origin + dir * max;
+        let (lo, hi) = (origin.min(end) - Vec2::splat(radius), origin.max(end) + Vec2::splat(radius));
+        let mut hits: Vec<RayHit> = self.colliders.iter()
+            .filter(|c| c.layer.intersects(layers))
+            .filter(|c| {
+                let (min, max) = c.shape.bounds();
+                min.x() <= hi.x() && min.y() <= hi.y() && max.x() >= lo.x() && max.y() >= lo.y()
+            })
+            .filter_map(|c| {
+                let (distance, normal) = shape::cast(&c.shape, origin, dir, max, radius)?;
+                Some(RayHit { key: c.key, layer: c.layer, distance, pos: origin + dir * distance, normal })
+            })
+            .collect();
+        // ties go to the lower key, so the same cast always finds the same thing
+        hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap().then(a.key.cmp(&b.key)));
+        hits
+    }
+
+    /// The first collider on one of `layers` a circle of `radius` swept from `origin` along `dir` for `max` would touch.
+    pub fn cast(&self, origin: Vec2, dir: Vec2, max: f32, radius: f32, layers: Layers) -> Option<RayHit> {
+        self.cast_all(origin, dir, max, radius, layers).into_iter().next()
     }
 
     pub fn collided(&mut self) -> impl ExactSizeIterator<Item = Collision> + '_ {
         self.collided.drain(..)
     }
+
+    /// How everything touching a sensor during the last call to `collide` changed since the call before,
+    /// sorted by trigger.
+    pub fn triggered(&mut self) -> impl ExactSizeIterator<Item = TriggerEvent> + '_ {
+        self.triggered.drain(..)
+    }
+}
+
+#[cfg(test)]
+fn brute_force(colliders: &[Collider]) -> Vec<Collision> {
+    let mut collided = vec![];
+    for (i, c0) in colliders.iter().enumerate() {
+        for c1 in &colliders[i + 1..] {
+            if c0.interacts(c1) {
+                collided.extend(narrowphase(c0, c1));
+            }
+        }
+    }
+    collided
+}
+
+/// Keys for `count` different cans.
+#[cfg(test)]
+fn can_keys(count: usize) -> Vec<ArenaKey> {
+    let mut slots = super::Slots::new();
+    (0..count).map(|_| ArenaKey::Can(slots.insert(()))).collect()
+}
+
+#[cfg(test)]
+fn scattered_cans(count: usize) -> Vec<Collider> {
+    use macroquad::prelude::vec2;
+    // spread out about as densely as the cans on the donut, in a fixed pseudo-random pattern
+    let side = (count as f32).sqrt() * 3.0;
+    let mut seed = 0x2545_f491_u32;
+    let mut rand = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as f32 / u32::MAX as f32
+    };
+    can_keys(count)
+        .into_iter()
+        .map(|key| Collider {
+            shape: Shape::Circle { pos: vec2(rand(), rand()) * side, radius: 0.5 },
+            key,
+            layer: Layers::CAN,
+            mask: Layers::ALL,
+        })
+        .collect()
+}
+
+#[test]
+fn equal_masses_trade_velocities_head_on() {
+    let cans = can_keys(2);
+    use macroquad::prelude::vec2;
+    let bouncy = Material { mass: 1.0, restitution: 1.0, friction: 0.0 };
+    let collision = Collision {
+        members: [cans[0], cans[1]],
+        normal: vec2(-1.0, 0.0),
+        depth: 0.0,
+        sensor: false,
+    };
+    let Resolution { impulses: [a, b], .. } = resolve(&collision, [
+        Body { vel: vec2(1.0, 0.0), material: bouncy },
+        Body { vel: vec2(0.0, 0.0), material: bouncy },
+    ]).unwrap();
+    assert_eq!(vec2(1.0, 0.0) + a, vec2(0.0, 0.0));
+    assert_eq!(b, vec2(1.0, 0.0));
+}
+
+#[test]
+fn layers_must_match_both_ways() {
+    let cans = can_keys(2);
+    use macroquad::prelude::vec2;
+    let circle = |key, layer, mask| Collider {
+        shape: Shape::Circle { pos: vec2(0.0, 0.0), radius: 1.0 },
+        key,
+        layer,
+        mask,
+    };
+    let hook = circle(ArenaKey::Hook, Layers::HOOK, Layers::GRABBABLE);
+    let can = circle(cans[0], Layers::CAN | Layers::GRABBABLE, Layers::ALL);
+    let car = circle(ArenaKey::Car, Layers::CAR, Layers::ALL);
+
+    let mut arena = CircleArena::new();
+    arena.collide(vec![hook, can, car].into_iter());
+    let members: Vec<_> = arena.collided().map(|c| c.members).collect();
+    assert!(members == vec![[ArenaKey::Hook, cans[0]], [cans[0], ArenaKey::Car]]);
+}
+
+#[test]
+fn sensors_report_enter_stay_exit() {
+    let cans = can_keys(2);
+    use macroquad::prelude::vec2;
+    let sensor = Collider {
+        shape: Shape::Circle { pos: vec2(0.0, 0.0), radius: 1.0 },
+        key: ArenaKey::Trigger(0),
+        layer: Layers::SENSOR,
+        mask: Layers::CAN,
+    };
+    let can = |x| Collider {
+        shape: Shape::Circle { pos: vec2(x, 0.0), radius: 0.5 },
+        key: cans[0],
+        layer: Layers::CAN,
+        mask: Layers::ALL,
+    };
+
+    let mut arena = CircleArena::new();
+    let mut overlaps = vec![];
+    for &x in &[3.0, 1.0, 0.0, 3.0, 3.0] {
+        arena.collide(vec![sensor, can(x)].into_iter());
+        overlaps.push(arena.triggered().map(|e| (e.trigger, e.other, e.overlap)).collect::<Vec<_>>());
+    }
+    let event = |overlap| vec![(ArenaKey::Trigger(0), cans[0], overlap)];
+    assert_eq!(overlaps, vec![vec![], event(Overlap::Enter), event(Overlap::Stay), event(Overlap::Exit), vec![]]);
+}
+
+#[test]
+fn broadphase_matches_brute_force() {
+    for &count in &[20, 500] {
+        let circles = scattered_cans(count);
+        let mut arena = CircleArena::new();
+        arena.collide(circles.iter().copied());
+        let found: Vec<_> = arena.collided().map(|c| c.members).collect();
+        let expected: Vec<_> = brute_force(&circles).into_iter().map(|c| c.members).collect();
+        assert!(!expected.is_empty());
+        assert!(found == expected, "{} circles: broadphase found {} pairs, brute force {}", count, found.len(), expected.len());
+    }
+}
+
+/// `cargo test --release -- --ignored --nocapture broadphase_benchmark`
+#[test]
+#[ignore]
+fn broadphase_benchmark() {
+    use std::time::Instant;
+    const RUNS: u32 = 20;
+    for &count in &[20, 500, 5000] {
+        let circles = scattered_cans(count);
+        let mut arena = CircleArena::new();
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            arena.collide(circles.iter().copied());
+            arena.collided().for_each(drop);
+        }
+        let grid = start.elapsed() / RUNS;
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            brute_force(&circles);
+        }
+        let brute = start.elapsed() / RUNS;
+
+        println!("{:>5} circles: grid {:>10?}, brute force {:>10?}", count, grid, brute);
+    }
+}
+
+#[test]
+fn casts_find_the_nearest_can_first() {
+    let cans = can_keys(2);
+    use macroquad::prelude::vec2;
+    let can = |i, x| Collider {
+        shape: Shape::Circle { pos: vec2(x, 0.0), radius: 0.5 },
+        key: cans[i],
+        layer: Layers::CAN | Layers::GRABBABLE,
+        mask: Layers::ALL,
+    };
+    let wall = Collider {
+        shape: Shape::Segment { a: vec2(2.0, -1.0), b: vec2(2.0, 1.0) },
+        key: ArenaKey::Wall(0),
+        layer: Layers::WALL,
+        mask: Layers::CAN,
+    };
+    let mut arena = CircleArena::new();
+    arena.collide(vec![can(0, 8.0), can(1, 4.0), wall].into_iter());
+
+    let hit = arena.cast(vec2(0.0, 0.0), vec2(1.0, 0.0), 20.0, 0.0, Layers::GRABBABLE).unwrap();
+    assert_eq!(hit.key, cans[1]);
+    assert!((hit.distance - 3.5).abs() < 1e-5);
+    assert_eq!(arena.cast(vec2(0.0, 0.0), vec2(1.0, 0.0), 20.0, 0.0, Layers::ALL).unwrap().key, ArenaKey::Wall(0));
+    let all: Vec<_> = arena.cast_all(vec2(0.0, 0.0), vec2(1.0, 0.0), 20.0, 0.1, Layers::ALL).iter().map(|h| h.key).collect();
+    assert_eq!(all, vec![ArenaKey::Wall(0), cans[1], cans[0]]);
+    assert!(arena.cast(vec2(0.0, 0.0), vec2(1.0, 0.0), 3.0, 0.1, Layers::GRABBABLE).is_none());
 }
@@ -0,0 +1,162 @@
+use macroquad::prelude::Vec2;
+use std::collections::{BTreeMap, BTreeSet};
+use super::{Handle, Hook, World, mode::{Round, SETTLED}, world::Event};
+
+/// How long there is to get every can off the road, in seconds.
+const TIME_LIMIT: f64 = 90.0;
+/// How long after one grab the next has to come to keep a combo going, in seconds.
+const COMBO_WINDOW: f64 = 4.0;
+/// Points for every can that ends up off the road, however it got there.
+const POINTS_PER_CAN: f32 = 100.0;
+/// Points for every meter a can flew after being let go of.
+const POINTS_PER_METER: f32 = 10.0;
+
+/// A can the hook has let go of, that hasn't come to rest yet.
+#[derive(Copy, Clone)]
+struct Fling {
+    from: Vec2,
+    /// The combo going when it was let go of.
+    multiplier: u32,
+}
+
+/// How a round of clearing went.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Results {
+    pub score: u32,
+    pub cleared: usize,
+    /// How many cans were on the road at some point, to be cleared off it.
+    pub total: usize,
+    /// How long the round lasted, in seconds.
+    pub time: f64,
+    pub best_combo: u32,
+}
+
+/// Get every can off the road before time runs out.
+/// Only cans that have been on the road count, so ones that started out on the grass aren't free points.
+/// Cans are worth more the further they're flung, and more again for every can grabbed in quick succession before it.
+#[derive(Clone)]
+pub struct Clearing {
+    started: f64,
+    /// Every can that's been on the road at some point, which are the ones that need clearing off it.
+    on_road: BTreeSet<Handle>,
+    flings: BTreeMap<Handle, Fling>,
+    cleared: BTreeSet<Handle>,
+    /// When the hook last grabbed something.
+    last_grab: f64,
+    /// How many grabs in a row have come within the combo window of each other.
+    combo: u32,
+    best_combo: u32,
+    round: Round<Results>,
+}
+impl Clearing {
+    pub fn new(world: &World) -> Self {
+        Self {
+            started: world.clock.now(),
+            on_road: world.cans.iter().filter(|(_, can)| world.map.on_road(can.pos)).map(|(handle, _)| handle).collect(),
+            flings: BTreeMap::new(),
+            cleared: BTreeSet::new(),
+            last_grab: f64::NEG_INFINITY,
+            combo: 0,
+            best_combo: 0,
+            round: Round::new(world.clock.now() + TIME_LIMIT),
+        }
+    }
+
+    /// Catches up on the World's latest step.
+    pub fn update(&mut self, world: &World) {
+        if self.round.over() {
+            return;
+        }
+        let now = world.clock.now();
+        if now - self.last_grab > COMBO_WINDOW {
+            self.combo = 0;
+        }
+
+        for event in &world.events {
+            match *event {
+                Event::Grab { .. } => {
+                    self.combo += 1;
+                    self.best_combo = self.best_combo.max(self.combo);
+                    self.last_grab = now;
+                }
+                Event::Release { can } => {
+                    if let Some(pos) = world.cans.get(can).map(|can| can.pos) {
+                        self.flings.insert(can, Fling { from: pos, multiplier: self.combo.max(1) });
+                    }
+                }
+                _ => {},
+            }
+        }
+
+        let held = match world.hook {
+            Hook::Locked { can, .. } => Some(can),
+            _ => None,
+        };
+        for (handle, can) in world.cans.iter() {
+            if world.map.on_road(can.pos) {
+                self.on_road.insert(handle);
+            }
+            if !self.on_road.contains(&handle) || self.cleared.contains(&handle) || held == Some(handle) || can.vel.length() > SETTLED {
+                continue;
+            }
+            let fling = self.flings.remove(&handle);
+            if !world.map.on_road(can.pos) {
+                self.cleared.insert(handle);
+                let (distance, multiplier) = fling.map_or((0.0, 1), |f| ((can.pos - f.from).length(), f.multiplier));
+                self.round.add((POINTS_PER_CAN + distance * POINTS_PER_METER) * multiplier as f32);
+            }
+        }
+
+        let time = now - self.started;
+        if self.cleared.len() == self.on_road.len() || time >= TIME_LIMIT {
+            self.round.end(Results {
+                score: self.round.score(),
+                cleared: self.cleared.len(),
+                total: self.on_road.len(),
+                time: time.min(TIME_LIMIT),
+                best_combo: self.best_combo,
+            });
+        }
+    }
+
+    /// How many cans have been cleared off the road, and how many have been on it to clear.
+    pub fn cleared(&self) -> (usize, usize) {
+        (self.cleared.len(), self.on_road.len())
+    }
+
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    pub fn round(&self) -> &Round<Results> {
+        &self.round
+    }
+}
+
+#[test]
+fn flung_cans_score_by_distance() {
+    use super::{Can, Cantainer, Input, Map};
+    use macroquad::prelude::vec2;
+    let mut world = World::new(Map::donut());
+    world.car.pos = vec2(0.0, 33.0);
+    world.cans = Cantainer::new(vec![Can::new(vec2(0.0, 29.0)), Can::new(vec2(0.0, 0.0))]);
+    let mut clearing = Clearing::new(&world);
+    world.step(Input::default());
+    clearing.update(&world);
+    // the can in the middle of the donut was never on the road to begin with, so there's nothing to clear it off
+    assert_eq!(clearing.cleared(), (0, 1));
+    assert_eq!(clearing.round().score(), 0);
+
+    // fling the other can clear across the grass, as if the hook had let go of it
+    let handle = world.cans.iter().map(|(h, _)| h).next().unwrap();
+    world.cans.get_mut(handle).unwrap().vel = vec2(0.0, -0.8);
+    world.events = vec![Event::Grab { can: handle }, Event::Release { can: handle }];
+    clearing.update(&world);
+    for _ in 0..600 {
+        world.step(Input::default());
+        clearing.update(&world);
+    }
+    let results = clearing.round().results().expect("every can is off the road");
+    assert_eq!((results.cleared, results.total, results.best_combo), (1, 1, 1));
+    assert!(results.score > 100, "got {}", results.score);
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeSet;
+use macroquad::prelude::Vec2;
+use super::{ArenaKey, Handle, Hook, World, circle::{Overlap, TriggerEvent}, mode::{Round, SETTLED}, world::Event};
+
+/// Seconds every delivery gets, however close the zone is.
+const BASE_TIME: f64 = 10.0;
+/// How far a can can be towed in a second, for working out how long a delivery should get.
+const TOWING_SPEED: f64 = 4.0;
+const POINTS_PER_DELIVERY: f32 = 100.0;
+/// Points for every second a delivery had left to spare.
+const POINTS_PER_SECOND: f32 = 10.0;
+/// Points lost for letting go of a can that then stops short of the zone.
+const DROP_PENALTY: f32 = 50.0;
+
+/// Why a round of delivering came to an end.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Outcome {
+    /// The clock ran out on a delivery.
+    TimeUp,
+    /// Every can there was got delivered.
+    AllDelivered,
+    /// The map has nowhere to deliver cans to.
+    NoZones,
+    /// No zone was left any further off than the last delivery.
+    OutOfZones,
+}
+
+/// How a round of delivering went.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Results {
+    pub outcome: Outcome,
+    pub score: u32,
+    pub delivered: usize,
+    pub drops: usize,
+}
+
+/// Tow cans to drop-off zones one at a time before the clock runs out on each,
+/// every zone further from where the last can was delivered than that delivery had to go,
+/// until there's no zone left far enough off.
+/// Cans count if they're in tow when they reach the zone, or were let go of and slide in on their own.
+#[derive(Clone)]
+pub struct Delivery {
+    target: Option<usize>,
+    /// Where the last can was delivered, or where the car started out before any were.
+    from: Vec2,
+    /// How far the current delivery is from `from`.
+    distance: f32,
+    delivered: usize,
+    drops: usize,
+    /// Cans the hook has let go of that are still sliding.
+    loose: BTreeSet<Handle>,
+    /// Runs out when the current delivery has to be made by.
+    round: Round<Results>,
+}
+impl Delivery {
+    pub fn new(world: &World) -> Self {
+        let mut delivery = Self {
+            target: None,
+            from: world.car.pos,
+            distance: 0.0,
+            delivered: 0,
+            drops: 0,
+            loose: BTreeSet::new(),
+            round: Round::new(0.0),
+        };
+        delivery.next(world);
+        delivery
+    }
+
+    /// The zone cans have to go to next, unless there's none left to go to.
+    pub fn target(&self) -> Option<usize> {
+        self.target
+    }
+
+    /// Picks the nearest zone that's further off than the last delivery was, if there's one left,
+    /// and starts the clock on it, giving it longer the further away it is.
+    fn next(&mut self, world: &World) {
+        let (from, last) = (self.from, self.distance);
+        let further = world.map.zones.iter()
+            .map(|&(pos, _)| (pos - from).length())
+            .enumerate()
+            .filter(|&(_, distance)| distance > last)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        self.target = further.map(|(zone, _)| zone);
+        if let Some((_, distance)) = further {
+            self.distance = distance;
+            self.round.extend(world.clock.now() + BASE_TIME + distance as f64 / TOWING_SPEED);
+        }
+    }
+
+    /// Catches up on the World's latest step, taking delivered cans out of it.
+    pub fn update(&mut self, world: &mut World) {
+        if self.round.over() {
+            return;
+        }
+        let now = world.clock.now();
+        let held = match world.hook {
+            Hook::Locked { can, .. } => Some(can),
+            _ => None,
+        };
+
+        let mut delivered = None;
+        for event in &world.events {
+            match *event {
+                Event::Release { can } => {
+                    self.loose.insert(can);
+                }
+                Event::Grab { can } => {
+                    self.loose.remove(&can);
+                }
+                Event::Trigger(TriggerEvent { trigger: ArenaKey::Zone(zone), other: ArenaKey::Can(can), overlap: Overlap::Enter })
+                    if Some(zone) == self.target() && (held == Some(can) || self.loose.contains(&can)) =>
+                {
+                    delivered = Some(can);
+                }
+                _ => {},
+            }
+        }
+        if let Some(can) = delivered {
+            self.round.add(POINTS_PER_DELIVERY + self.round.time_left(now) as f32 * POINTS_PER_SECOND);
+            self.delivered += 1;
+            self.loose.remove(&can);
+            if let Some(can) = world.cans.remove(can) {
+                self.from = can.pos;
+            }
+            self.next(world);
+        }
+
+        let cans = &world.cans;
+        let before = self.loose.len();
+        self.loose.retain(|&can| matches!(cans.get(can), Some(can) if can.vel.length() > SETTLED));
+        let dropped = before - self.loose.len();
+        self.drops += dropped;
+        self.round.add(-(dropped as f32) * DROP_PENALTY);
+
+        let outcome = if world.map.zones.is_empty() {
+            Some(Outcome::NoZones)
+        } else if world.cans.values().next().is_none() {
+            Some(Outcome::AllDelivered)
+        } else if self.target.is_none() {
+            Some(Outcome::OutOfZones)
+        } else if now > self.round.deadline() {
+            Some(Outcome::TimeUp)
+        } else {
+            None
+        };
+        if let Some(outcome) = outcome {
+            self.round.end(Results {
+                outcome,
+                score: self.round.score(),
+                delivered: self.delivered,
+                drops: self.drops,
+            });
+        }
+    }
+
+    pub fn delivered(&self) -> usize {
+        self.delivered
+    }
+
+    pub fn round(&self) -> &Round<Results> {
+        &self.round
+    }
+}
+
+#[test]
+fn towing_a_can_into_the_zone_delivers_it() {
+    use super::{Can, Cantainer, Input, Map, world::hooked};
+    use macroquad::prelude::vec2;
+    let mut world = World::new(Map::donut());
+    world.cans = Cantainer::new(vec![Can::new(vec2(0.0, 29.0)), Can::new(vec2(0.0, -29.0))]);
+    let mut delivery = Delivery::new(&world);
+    let zone = delivery.target().unwrap();
+    let handle = world.cans.iter().map(|(h, _)| h).next().unwrap();
+
+    // let go of a can well short of the zone
+    world.step(Input::default());
+    world.events = vec![Event::Release { can: handle }];
+    delivery.update(&mut world);
+    assert_eq!((delivery.drops, delivery.round().score()), (1, 0));
+
+    // then tow one right in
+    let (center, _) = world.map.zones[zone];
+    world.car.pos = center + vec2(0.0, 4.0);
+    let handle = hooked(&mut world, center);
+    world.step(Input::default());
+    delivery.update(&mut world);
+    assert_eq!(delivery.delivered(), 1);
+    assert!(delivery.round().score() > 100);
+    assert_ne!(delivery.target(), Some(zone));
+    assert!(world.cans.get(handle).is_none());
+    assert!(delivery.round().results().is_none());
+}
+
+#[test]
+fn delivering_without_zones_ends_straight_away() {
+    use super::{Input, Map};
+    let mut world = World::new(Map::donut());
+    world.map.zones = vec![];
+    let mut delivery = Delivery::new(&world);
+    world.step(Input::default());
+    delivery.update(&mut world);
+    assert_eq!(delivery.round().results().map(|r| r.outcome), Some(Outcome::NoZones));
+}
+
+#[test]
+fn each_delivery_goes_further_than_the_last() {
+    use super::{Can, Cantainer, Input, Map, world::hooked};
+    use macroquad::prelude::vec2;
+    let mut world = World::new(Map::donut());
+    world.cans = Cantainer::new((0..8).map(|i| Can::new(vec2(i as f32, 0.0))).collect());
+    let mut delivery = Delivery::new(&world);
+    let mut from = world.car.pos;
+    let mut distances = vec![];
+    while let Some(zone) = delivery.target() {
+        let (center, _) = world.map.zones[zone];
+        distances.push((center - from).length());
+        world.car.pos = center + vec2(0.0, 4.0);
+        hooked(&mut world, center);
+        world.step(Input::default());
+        delivery.update(&mut world);
+        assert_eq!(delivery.delivered(), distances.len());
+        from = center;
+    }
+    assert!(distances.len() > 1, "only delivered {:?}", distances);
+    assert!(distances.windows(2).all(|pair| pair[1] > pair[0]), "{:?} don't keep getting further", distances);
+    assert_eq!(delivery.round().results().map(|r| r.outcome), Some(Outcome::OutOfZones));
+}
@@ -0,0 +1,40 @@
+use super::{input::{InputSource, Scripted}, mode::Kind, render::Snapshot, Map, Replay, World};
+
+/// A past run played back in a World of its own alongside the player's, in whatever Mode it was played in.
+/// Keeping it separate means nothing the ghost does can bump into the player, or the other way around.
+pub struct Ghost {
+    pub world: World,
+    pub prev: Snapshot,
+    playback: Scripted,
+    steps_left: usize,
+}
+impl Ghost {
+    pub fn new(map: Map, replay: &Replay) -> Self {
+        let mut world = World::new(map);
+        world.mode = replay.mode.start(&world);
+        Self {
+            prev: Snapshot::of(&world),
+            playback: replay.playback(),
+            steps_left: replay.steps(),
+            world,
+        }
+    }
+
+    /// Steps the ghost's World once, unless the run it's replaying already ended,
+    /// in which case it stays parked wherever it finished.
+    pub fn step(&mut self) {
+        if self.steps_left == 0 {
+            self.prev = Snapshot::of(&self.world);
+            return;
+        }
+        self.steps_left -= 1;
+        self.prev = Snapshot::of(&self.world);
+        let input = self.playback.input(&self.world);
+        self.world.step(input);
+    }
+}
+
+/// Where the quickest run on a map in a given Mode is kept between sessions.
+pub fn best_run_path(map: &Map, mode: Kind) -> String {
+    format!("best-{}-{}.replay", map.name(), mode.name())
+}
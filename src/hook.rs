@@ -1,10 +1,46 @@
 use macroquad::prelude::*;
-use super::{Can, Circle, ArenaKey, math::*};
+use super::{Can, Cantainer, Car, Chain, CircleArena, Map, Handle, Collider, ArenaKey, Layers, Shape, SimClock, circle::RayHit, math::*, world::Event};
 use std::f32::consts::{FRAC_2_PI, FRAC_PI_2};
 
 /// How deep in he claw what's being held should go.
 const GRIP_DEPTH: f32 = 0.88;
-#[derive(Copy, Clone)]
+const LAUNCH_SPEED: f32 = 1.25;
+/// How much of its speed a launched hook keeps each step.
+const FLIGHT_DRAG: f32 = 0.82;
+/// How far ahead of the hook's base the tips of its claws are.
+const CLAW_REACH: f32 = 0.85;
+const CLAW_RADIUS: f32 = 0.1;
+
+/// How fast the chain winds in and out, and how far it goes either way.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Winch {
+    /// How much chain winds in or out each step, in meters.
+    pub speed: f32,
+    /// As short as the chain goes, which holds a can right up against the back bumper.
+    pub min_length: f32,
+    /// As much chain as there is to let out.
+    pub max_length: f32,
+}
+impl Default for Winch {
+    fn default() -> Self {
+        Self {
+            speed: 0.08,
+            min_length: 0.4,
+            max_length: 12.0,
+        }
+    }
+}
+
+/// A corner the chain is wrapped around on its way from the hook to the car.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Wrap {
+    pub pos: Vec2,
+    /// Which way the chain bent around the corner when it caught: the sign of the cross product
+    /// of the chain going into the corner and the chain coming out of it.
+    pub side: f32,
+}
+
+#[derive(Clone, Debug)]
 pub enum Hook {
     Retracting {
         pos: Vec2,
@@ -21,15 +57,27 @@ pub enum Hook {
         end: Vec2,
         facing: Vec2,
         chain_length: f32,
-        can_index: usize,
+        can: Handle,
         can_offset: Vec2,
         vel: Vec2,
+        /// The corners the chain is caught on, starting from the hook's end.
+        wraps: Vec<Wrap>,
+    },
+    /// Latched onto one of the Map's anchor posts, which swings the car around it on the chain.
+    Anchored {
+        end: Vec2,
+        facing: Vec2,
+        chain_length: f32,
+        wraps: Vec<Wrap>,
     },
     Ready {
         facing: Vec2,
     },
 }
 impl Hook {
+    /// How far from the dock a launched hook's claws get before it runs out of steam.
+    pub const REACH: f32 = LAUNCH_SPEED * FLIGHT_DRAG / (1.0 - FLIGHT_DRAG) + CLAW_REACH;
+
     pub fn new() -> Self {
         Hook::Ready { facing: Vec2::unit_x() }
     }
@@ -44,35 +92,57 @@ impl Hook {
         if let &mut Hook::Ready { facing } = self {
             *self = Hook::Launched {
                 pos: dock,
-                vel: 1.25,
+                vel: LAUNCH_SPEED,
                 facing,
             };
         }
     }
 
-    pub fn retract(&mut self) {
+    pub fn retract(&mut self, clock: &SimClock) {
         use Hook::*;
-        if let Launched { pos, facing, .. } | Locked { end: pos, facing, .. } = *self {
+        if let Launched { pos, facing, .. } | Locked { end: pos, facing, .. } | Anchored { end: pos, facing, .. } = *self {
             *self = Retracting {
                 reached: pos,
                 pos,
                 facing,
-                started: get_time(),
+                started: clock.now(),
             };
         }
     }
 
-    pub fn fly(&mut self, dock: Vec2) {
+    /// What the claws of a hook at `pos` pointing along `facing` would first run into over the next `distance`:
+    /// either something to grab, or a wall in the way of it.
+    fn sweep(arena: &CircleArena, pos: Vec2, facing: Vec2, distance: f32) -> Option<RayHit> {
+        arena.cast(pos + facing * CLAW_REACH, facing, distance, CLAW_RADIUS, Layers::GRABBABLE | Layers::WALL)
+    }
+
+    /// What a hook fired from `dock` along `facing` would run into, going as far as it ever could.
+    pub fn aim(arena: &CircleArena, dock: Vec2, facing: Vec2) -> Option<RayHit> {
+        Self::sweep(arena, dock, facing, Self::REACH - CLAW_REACH)
+    }
+
+    /// Moves the hook along for a step. A launched hook sweeps its claws over everything between
+    /// where they were and where they're going, stopping at and returning the first thing they'd grab,
+    /// so it can't fly through a can in a single step no matter how fast it's going.
+    /// Running into a wall first, it comes straight back instead.
+    pub fn fly(&mut self, dock: Vec2, clock: &SimClock, arena: &CircleArena) -> Option<RayHit> {
         match self {
             Hook::Launched { vel, pos, facing, } => {
-                *vel *= 0.82;
-                *pos += *facing * *vel;
-                if *vel < 0.00001 {
-                    self.retract()
+                *vel *= FLIGHT_DRAG;
+                let hit = Self::sweep(arena, *pos, *facing, *vel);
+                *pos += *facing * hit.map_or(*vel, |hit| hit.distance);
+                match hit {
+                    Some(hit) if !hit.layer.intersects(Layers::GRABBABLE) => {
+                        self.retract(clock);
+                        return None;
+                    }
+                    None if *vel < 0.00001 => self.retract(clock),
+                    _ => {},
                 }
+                return hit;
             },
             Hook::Retracting { pos, facing, reached, started, .. } => {
-                let delta = smoothstep((get_time() - *started) as f32);
+                let delta = smoothstep((clock.now() - *started) as f32);
                 *pos = reached.lerp(dock, delta);
                 if delta >= 1.0 {
                     *self = Hook::Ready { facing: *facing };
@@ -80,9 +150,30 @@ impl Hook {
             }
             _ => {},
         }
+        None
+    }
+
+    /// Closes the claws of a launched hook on whatever `key` is, as long as it's something they can hold onto:
+    /// a can, which a Grab is returned for, or an anchor post.
+    pub fn grab(&mut self, dock: Vec2, key: ArenaKey, cans: &mut Cantainer, map: &Map) -> Option<Event> {
+        if !matches!(self, Hook::Launched { .. }) {
+            return None;
+        }
+        match key {
+            ArenaKey::Can(handle) => {
+                self.lock(dock, handle, cans.get_mut(handle)?);
+                Some(Event::Grab { can: handle })
+            }
+            ArenaKey::Anchor(i) => {
+                let (center, radius) = map.anchors[i];
+                self.anchor(dock, center, radius);
+                None
+            }
+            _ => None,
+        }
     }
 
-    pub fn lock(&mut self, dock: Vec2, can_index: usize, can: &mut Can) {
+    fn lock(&mut self, dock: Vec2, handle: Handle, can: &mut Can) {
         if let Hook::Launched { pos, .. } = *self {
             let can_offset = (pos - can.pos).normalize() * GRIP_DEPTH;
             *self = Hook::Locked {
@@ -91,128 +182,280 @@ impl Hook {
                 end: can.pos + can_offset,
                 vel: Vec2::zero(),
                 can_offset,
-                can_index,
+                can: handle,
+                wraps: vec![],
             };
         }
     }
 
-    pub fn drag(&mut self, dock: Vec2, can: &mut Can) {
-        if let Hook::Locked { end, facing, chain_length, vel, .. } = self {
-            // drag hook towards car
-            let delta = *end - dock;
-            let hook_dist = delta.length();
-            *chain_length = chain_length.min(hook_dist).max(1.5);
+    /// Latches onto the anchor post at `center`, claws closed around it the same as they would a can.
+    fn anchor(&mut self, dock: Vec2, center: Vec2, radius: f32) {
+        if let Hook::Launched { pos, .. } = *self {
+            let out = (pos - center).normalize();
+            // a can's half a meter across, so the claws go just as far past the edge of a post
+            let end = center + out * (radius + GRIP_DEPTH - 0.5);
+            *self = Hook::Anchored {
+                facing: -out,
+                chain_length: (dock - end).length(),
+                end,
+                wraps: vec![],
+            };
+        }
+    }
 
-            if hook_dist > *chain_length {
-                let pull = delta / hook_dist;
-                *vel += pull * (*chain_length - hook_dist);
+    /// Catches the chain on any corner it swept over as the dock moved from `from` to `dock`,
+    /// and lets go of any it's since swung back around off of.
+    fn wrap(wraps: &mut Vec<Wrap>, end: Vec2, mut from: Vec2, dock: Vec2, map: &Map) {
+        while let Some(&Wrap { pos, side }) = wraps.last() {
+            let before = wraps.iter().rev().nth(1).map_or(end, |w| w.pos);
+            if cross(pos - before, dock - pos) * side >= 0.0 {
+                break;
             }
+            wraps.pop();
+        }
 
-            *vel *= 0.98;
-            *end += *vel;
+        let inside = |p: Vec2, a: Vec2, b: Vec2, c: Vec2| {
+            let (x, y, z) = (cross(b - a, p - a), cross(c - b, p - b), cross(a - c, p - c));
+            (x > 0.0 && y > 0.0 && z > 0.0) || (x < 0.0 && y < 0.0 && z < 0.0)
+        };
+        loop {
+            let pivot = wraps.last().map_or(end, |w| w.pos);
+            // of all the corners the chain swept over, it caught on whichever it came to first
+            let swept = (from - pivot).normalize();
+            let caught = map.corners()
+                .filter(|&c| c != pivot && inside(c, pivot, from, dock))
+                .max_by(|&a, &b| {
+                    let turn = |c: Vec2| (c - pivot).normalize().dot(swept);
+                    turn(a).partial_cmp(&turn(b)).unwrap()
+                });
+            match caught {
+                Some(pos) => {
+                    wraps.push(Wrap { pos, side: cross(pos - pivot, dock - pos).signum() });
+                    // carry on sweeping from where the chain was when it caught
+                    let d = pos - pivot;
+                    from = from.lerp(dock, cross(d, pivot - from) / cross(d, dock - from));
+                }
+                None => break,
+            }
+        }
+    }
 
-            // drag can with hook
-            let delta = can.pos - *end;
-            let can_dist = delta.length();
+    /// Winds the chain in or out by `reel` of the `winch`'s speed, then pulls it along after the car,
+    /// catching it on the map's corners along the way, and pulls whatever the hook's holding after it,
+    /// `weight` being one over its mass.
+    /// Pulled tight, the chain yanks the hook end and the car towards each other, the lighter of the two the most,
+    /// so something heavy holds the car back and something with no weight to it at all won't budge.
+    /// Returns whether the chain was pulled tight.
+    fn haul(&mut self, car: &mut Car, chain: &mut Chain, map: &Map, weight: f32, reel: f32, winch: &Winch) -> bool {
+        let mut still = Vec2::zero();
+        let (end, vel, chain_length, wraps) = match self {
+            Hook::Locked { end, vel, chain_length, wraps, .. } => (end, vel, chain_length, wraps),
+            Hook::Anchored { end, chain_length, wraps, .. } => (end, &mut still, chain_length, wraps),
+            _ => return false,
+        };
+        Self::wrap(wraps, *end, chain.dock(), car.dock(), map);
+        // the chain's only free to move between the hook and the corner nearest it,
+        // it's pulled tight around the rest
+        let around = |dock: Vec2| -> Vec<Vec2> { Some(dock).into_iter().chain(wraps.iter().rev().map(|w| w.pos)).collect() };
+        let length = |points: &[Vec2]| -> f32 { points.windows(2).map(|pair| (pair[1] - pair[0]).length()).sum() };
+        let path = around(car.dock());
+        let pivot = path[path.len() - 1];
+
+        // winding in takes up whatever slack there is before it starts pulling
+        if reel < 0.0 {
+            *chain_length = chain_length.min(length(&path) + (*end - pivot).length());
+        }
+        *chain_length = (*chain_length + reel * winch.speed).clamp(winch.min_length, winch.max_length);
+
+        let was = *end;
+        let mut next = *end + *vel * 0.98;
+        let taut = length(&path) + (next - pivot).length() - *chain_length;
+        if taut > 0.0 {
+            let car_weight = Car::MATERIAL.mass.recip();
+            let total = car_weight + weight;
+            // which way the chain pulls on either end
+            let to_car = (path.get(1).copied().unwrap_or(next) - car.dock()).normalize();
+            let to_end = (pivot - next).normalize();
+            // whatever speed the two were pulling apart at, the chain takes back
+            let apart = (-car.velocity().dot(to_car) - vel.dot(to_end)).max(0.0) / total;
+            car.bump(to_car * apart * car_weight, to_car * taut * car_weight / total);
+            next += to_end * taut * weight / total;
+        }
+
+        let path = around(car.dock());
+        *end = chain.step(&path, next, (*chain_length - length(&path)).max(0.0), weight > 0.0);
+        *vel = *end - was;
+        taut > 0.0
+    }
 
-            let pull = delta / can_dist;
+    /// Hauls the can along behind the chain.
+    pub fn drag(&mut self, car: &mut Car, can: &mut Can, chain: &mut Chain, map: &Map, reel: f32, winch: &Winch) {
+        // the hook end carries on wherever the can's already sliding
+        if let Hook::Locked { vel, .. } = self {
+            *vel = can.vel;
+        }
+        self.haul(car, chain, map, can.material.mass.recip(), reel, winch);
+        if let Hook::Locked { end, facing, .. } = self {
+            // and the chain's pull on it goes into the can's speed, for it to keep once the chain goes slack
+            let pull = (can.pos - *end).normalize();
             *facing = pull;
-            can.pos = *end + pull * GRIP_DEPTH;
+            can.vel = *end + pull * GRIP_DEPTH - can.pos;
         }
     }
 
-    pub fn release(&mut self, can: &mut Can) {
-        if let Hook::Locked { vel, .. } = *self {
-            can.vel += vel * 1.4;
-            self.retract();
+    /// Swings the car around the anchor post on the end of the chain.
+    /// However the chain turns the car's motion, the car turns to point along with it, rather than stalling against the chain sideways.
+    pub fn swing(&mut self, car: &mut Car, chain: &mut Chain, map: &Map, reel: f32, winch: &Winch) {
+        let was = car.vel;
+        if self.haul(car, chain, map, 0.0, reel, winch) {
+            let turn = cross(was, car.vel).atan2(was.dot(car.vel));
+            // the chain holds the car by its dock, so that's what it turns around
+            let dock = car.dock();
+            car.dir = angle_to_vec(vec_to_angle(car.dir) + turn);
+            car.pos += dock - car.dock();
         }
     }
 
-    pub fn draw_hook(&mut self, dock: Vec2) {
-        fn hook(dock: Vec2, facing: Vec2, twist: f32) {
-            fn claw(mut base: Vec2, tip: f32, dir: f32) {
+    pub fn release(&mut self, can: &mut Can, clock: &SimClock) {
+        if let Hook::Locked { .. } = *self {
+            // already going wherever it was being towed, and flung off a little faster still
+            can.vel *= 1.4;
+            self.retract(clock);
+        }
+    }
+
+    /// The hook as it was `t` of the way from `prev` to `self`, for rendering between steps.
+    /// If the hook changed states between the two, there's nothing to blend, so `self` is used as is.
+    pub fn interpolate(&self, prev: &Hook, t: f32) -> Hook {
+        use Hook::*;
+        match (self, prev) {
+            (&Launched { pos, vel, facing }, &Launched { pos: then, .. }) => Launched {
+                pos: then.lerp(pos, t),
+                vel,
+                facing,
+            },
+            (&Retracting { pos, reached, facing, started }, &Retracting { pos: then, .. }) => Retracting {
+                pos: then.lerp(pos, t),
+                reached,
+                facing,
+                started,
+            },
+            (Locked { end, facing, chain_length, can, can_offset, vel, wraps }, &Locked { end: then, facing: was, .. }) => Locked {
+                end: then.lerp(*end, t),
+                facing: slerp(was, *facing, t),
+                chain_length: *chain_length,
+                can: *can,
+                can_offset: *can_offset,
+                vel: *vel,
+                wraps: wraps.clone(),
+            },
+            (&Ready { facing }, &Ready { facing: was }) => Ready { facing: slerp(was, facing, t) },
+            (now, _) => now.clone(),
+        }
+    }
+
+    pub fn draw_hook(&mut self, dock: Vec2, clock: &SimClock, color: Color) {
+        fn hook(dock: Vec2, facing: Vec2, twist: f32, color: Color) {
+            fn claw(mut base: Vec2, tip: f32, dir: f32, color: Color) {
                 let out = angle_to_vec(tip + FRAC_PI_2 * dir);
                 let claw_blade = angle_to_vec(tip + FRAC_2_PI * dir);
                 base += out * 0.1;
 
-                fn tri(base: Vec2, x: Vec2, y: Vec2, z: Vec2) {
-                    draw_triangle(x + base, y + base, z + base, LIGHTGRAY)
+                fn tri(base: Vec2, x: Vec2, y: Vec2, z: Vec2, color: Color) {
+                    draw_triangle(x + base, y + base, z + base, color)
                 }
-                tri(base, angle_to_vec(tip) * 0.92, claw_blade * 0.34, Vec2::zero());
-            };
+                tri(base, angle_to_vec(tip) * 0.92, claw_blade * 0.34, Vec2::zero(), color);
+            }
             let (x, y) = (dock + facing * 0.05).into();
-            draw_circle(x, y, 0.17, LIGHTGRAY);
+            draw_circle(x, y, 0.17, color);
 
             let facing_angle = vec_to_angle(facing);
             let claw_dock = dock - facing * 0.07;
-            claw(claw_dock, facing_angle + twist, -1.0);
-            claw(claw_dock, facing_angle - twist, 1.0);
+            claw(claw_dock, facing_angle + twist, -1.0, color);
+            claw(claw_dock, facing_angle - twist, 1.0, color);
         }
 
-        let squeeze = ((get_time() * 7.5).sin() as f32) * 0.01;
+        let now = clock.now();
+        let squeeze = ((now * 7.5).sin() as f32) * 0.01;
         match *self {
-            Hook::Ready { facing } => hook(dock, facing, squeeze),
+            Hook::Ready { facing } => hook(dock, facing, squeeze, color),
             Hook::Retracting { started, pos, facing, .. } => hook(
                 pos,
                 facing,
-                lerp(-0.4, 0.0, smoothstep((get_time() - started) as f32)) + squeeze
+                lerp(-0.4, 0.0, smoothstep((now - started) as f32)) + squeeze,
+                color,
             ),
-            Hook::Launched { pos, facing, .. } => hook(pos, facing, -0.4 + squeeze),
-            Hook::Locked { end, facing, .. } => hook(end, facing, -0.435),
+            Hook::Launched { pos, facing, .. } => hook(pos, facing, -0.4 + squeeze, color),
+            Hook::Locked { end, facing, .. } | Hook::Anchored { end, facing, .. } => hook(end, facing, -0.435, color),
         }
     }
 
-    pub fn draw_chain(&mut self, dock: Vec2) {
-        fn chain(start: Vec2, end: Vec2) {
-            const LINK_LENGTH: f32 = 0.35;
-            const LINK_WIDTH: f32 = 0.12;
-            const LINK_OVERLAP: f32 = 0.12;
-            let normal = (start - end).normalize() * LINK_LENGTH;
-            let out_dir = angle_to_vec(vec_to_angle(normal) + FRAC_PI_2);
-            let out = out_dir * LINK_WIDTH;
-            let link_count = (start - end).length() / LINK_LENGTH;
-
-            fn line(s: Vec2, e: Vec2) {
-                let (x, y) = s.into();
-                let (w, z) = e.into();
-                draw_line(x, y, w, z, 0.07, LIGHTGRAY);
-            }
-
-            for link in 0..link_count as usize {
-                let w = out_dir * (link as f32 * 0.125 + get_time() as f32 * 4.0).sin() * 0.01;
-                let start_middle = end + normal * (link as f32 - LINK_OVERLAP) + w;
-                let end_middle = end + normal * ((link + 1) as f32 + LINK_OVERLAP);
-                if link % 2 == 0 {
-                    line(start_middle, end_middle);
-                } else {
-                    line(start_middle + out, start_middle - out);
-                    line(end_middle + out, end_middle - out);
-                    line(start_middle + out, end_middle + out);
-                    line(start_middle - out, end_middle - out);
-                }
-            }
-
-            line(end + normal * link_count.floor(), start);
-        }
-
-        match *self {
-            Hook::Launched { pos, .. } => chain(dock, pos),
-            Hook::Retracting { pos, .. } => chain(dock, pos),
-            Hook::Locked { end, .. } => chain(dock, end),
-            _ => {},
-        }
-    }
-
-    pub fn circles(&self) -> impl Iterator<Item = Circle> {
+    pub fn colliders(&self) -> impl Iterator<Item = Collider> {
         use Hook::*;
         match *self {
-            Ready { .. } | Retracting { .. } | Locked { .. } => None,
-            Launched { facing, pos, .. } => Some(Circle {
-                pos: pos + facing * 0.75,
-                radius: 0.1,
+            Ready { .. } | Retracting { .. } | Locked { .. } | Anchored { .. } => None,
+            Launched { facing, pos, .. } => Some(Collider {
+                // along the tips of the claws
+                shape: Shape::Capsule {
+                    a: pos + facing * 0.6,
+                    b: pos + facing * CLAW_REACH,
+                    radius: CLAW_RADIUS,
+                },
                 key: ArenaKey::Hook,
+                layer: Layers::HOOK,
+                mask: Layers::GRABBABLE,
             }),
         }
         .into_iter()
     }
 }
+
+#[test]
+fn chains_wrap_around_corners_and_unwrap_when_swung_back() {
+    use super::{map::Wall, world::hooked, Cantainer, World};
+    let mut world = World::new(Map::donut());
+    world.map.walls = vec![Wall { points: vec![vec2(2.0, 0.0), vec2(2.0, -5.0)], closed: false, restitution: 0.5 }];
+    world.cans = Cantainer::new(vec![]);
+    world.car.dir = vec2(1.0, 0.0);
+    world.car.pos = vec2(4.0, 1.0) + world.car.dir * 0.44;
+    let handle = hooked(&mut world, vec2(-0.88, 0.0));
+    let World { map, car, hook, chain, cans, winch, .. } = &mut world;
+    let can = cans.get_mut(handle).unwrap();
+    let wraps = |hook: &Hook| match hook {
+        Hook::Locked { wraps, .. } => wraps.iter().map(|w| w.pos).collect::<Vec<_>>(),
+        other => panic!("hook let go: {:?}", other),
+    };
+
+    // swing down past the end of the wall, slowly, so the can doesn't get flung round the corner after the car
+    for i in 1..=60 {
+        car.pos = vec2(4.0, 1.0 - 0.05 * i as f32) + car.dir * 0.44;
+        hook.drag(car, can, chain, map, 0.0, winch);
+        can.slide(1.0 - map.surface(can.pos).can_drag);
+    }
+    assert_eq!(wraps(hook), vec![vec2(2.0, 0.0)]);
+    // pulled tight around the corner, rather than cutting straight across it
+    let (path, straight, was) = match *hook {
+        Hook::Locked { end, chain_length, .. } => {
+            ((end - vec2(2.0, 0.0)).length() + (car.dock() - vec2(2.0, 0.0)).length(), (end - car.dock()).length(), chain_length)
+        }
+        _ => panic!("hook let go"),
+    };
+    assert!(path <= was + 1e-3, "the chain's path, {}, is longer than the chain, {}", path, was);
+    // so winding it in takes up the chain that's wrapped around the corner, not the shortcut across it
+    assert!(straight < path - winch.speed);
+    hook.drag(car, can, chain, map, -1.0, winch);
+    match &*hook {
+        Hook::Locked { chain_length, .. } => {
+            assert!((chain_length - (path.min(was) - winch.speed)).abs() < 1e-4, "wound in to {} from a path of {}", chain_length, path);
+        }
+        other => panic!("hook let go: {:?}", other),
+    }
+
+    // and back up again
+    for i in 1..=60 {
+        car.pos = vec2(4.0, -2.0 + 0.05 * i as f32) + car.dir * 0.44;
+        hook.drag(car, can, chain, map, 0.0, winch);
+        can.slide(1.0 - map.surface(can.pos).can_drag);
+    }
+    assert!(wraps(hook).is_empty());
+}
@@ -0,0 +1,183 @@
+use macroquad::prelude::*;
+use super::{render, World, math::*};
+
+/// Everything the player can ask of the car and hook during one simulation step.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Input {
+    /// Whether the gas is held down.
+    pub throttle: bool,
+    /// How hard to turn, from -1.0 (A) to 1.0 (D).
+    pub steer: f32,
+    /// The place in the world the hook should point towards.
+    pub aim: Vec2,
+    /// Launches the hook, or lets go of whatever it's holding.
+    pub fire: bool,
+    /// How fast to wind the chain, from -1.0 (Q, all the way in) to 1.0 (E, all the way out).
+    pub reel: f32,
+}
+
+/// Anything that can decide what to do with the car and hook.
+pub trait InputSource {
+    /// Asked once before every simulation step, with the World as it is before that step.
+    fn input(&mut self, world: &World) -> Input;
+}
+
+/// W to drive, A and D to steer, the mouse to aim, left click to fire, and Q and E to wind the chain in and out.
+pub struct KeyboardMouse;
+impl InputSource for KeyboardMouse {
+    fn input(&mut self, world: &World) -> Input {
+        Input {
+            throttle: is_key_down(KeyCode::W),
+            steer: match (is_key_down(KeyCode::A), is_key_down(KeyCode::D)) {
+                (true, false) => -1.0,
+                (false, true) => 1.0,
+                _ => 0.0,
+            },
+            aim: render::camera(&world.car).screen_to_world(mouse_position().into()),
+            fire: is_mouse_button_down(MouseButton::Left),
+            reel: match (is_key_down(KeyCode::Q), is_key_down(KeyCode::E)) {
+                (true, false) => -1.0,
+                (false, true) => 1.0,
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+/// The right trigger to drive, the left stick to steer, the right stick to aim, the right bumper to fire,
+/// and the left trigger and bumper to wind the chain in and out, on whichever gamepad was used last.
+/// Macroquad doesn't read gamepads at all, so they come from gilrs instead.
+#[cfg(feature = "gamepad")]
+pub struct Gamepad {
+    gilrs: gilrs::Gilrs,
+    active: Option<gilrs::GamepadId>,
+}
+#[cfg(feature = "gamepad")]
+impl Gamepad {
+    pub fn new() -> Result<Self, Box<gilrs::Error>> {
+        Ok(Self {
+            gilrs: gilrs::Gilrs::new().map_err(Box::new)?,
+            active: None,
+        })
+    }
+}
+#[cfg(feature = "gamepad")]
+impl InputSource for Gamepad {
+    fn input(&mut self, world: &World) -> Input {
+        use gilrs::{Axis, Button};
+        /// How far from the car the hook's aimed, with the right stick all the way over.
+        const AIM_REACH: f32 = 8.0;
+        while let Some(gilrs::Event { id, .. }) = self.gilrs.next_event() {
+            self.active = Some(id);
+        }
+        let car = &world.car;
+        // with the right stick let go of, the hook points straight ahead
+        let ahead = car.pos + car.dir * AIM_REACH;
+        let pad = match self.active {
+            Some(id) => self.gilrs.gamepad(id),
+            None => return Input { aim: ahead, ..Default::default() },
+        };
+
+        // the camera turns with the car, so the stick aims around which way the car's pointing
+        let stick = vec2(pad.value(Axis::RightStickX), pad.value(Axis::RightStickY));
+        Input {
+            throttle: pad.is_pressed(Button::RightTrigger2),
+            steer: pad.value(Axis::LeftStickX),
+            aim: if stick == Vec2::zero() {
+                ahead
+            } else {
+                car.pos + (car.dir * stick.y() + perp_vec(car.dir) * stick.x()) * AIM_REACH
+            },
+            fire: pad.is_pressed(Button::RightTrigger),
+            reel: match (pad.is_pressed(Button::LeftTrigger2), pad.is_pressed(Button::LeftTrigger)) {
+                (true, false) => -1.0,
+                (false, true) => 1.0,
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+/// Plays back a fixed list of inputs, each held for some number of steps,
+/// then does nothing once it runs out.
+pub struct Scripted {
+    steps: std::vec::IntoIter<(usize, Input)>,
+    current: Option<(usize, Input)>,
+}
+impl Scripted {
+    pub fn new(steps: Vec<(usize, Input)>) -> Self {
+        Self {
+            steps: steps.into_iter(),
+            current: None,
+        }
+    }
+}
+impl InputSource for Scripted {
+    fn input(&mut self, _: &World) -> Input {
+        loop {
+            match &mut self.current {
+                Some((0, _)) | None => {
+                    self.current = self.steps.next();
+                    if self.current.is_none() {
+                        return Input::default();
+                    }
+                }
+                Some((left, input)) => {
+                    *left -= 1;
+                    return *input;
+                }
+            }
+        }
+    }
+}
+
+/// Drives around the track on its own, steering towards a point a little way down the road.
+#[derive(Default)]
+pub struct Bot {
+    /// How far along the road the car was last time, so it keeps to the same stretch where the track crosses itself.
+    along: Option<f32>,
+}
+impl InputSource for Bot {
+    fn input(&mut self, world: &World) -> Input {
+        const LOOK_AHEAD: f32 = 6.0;
+        let car = &world.car;
+        let along = world.map.follow(car.pos, self.along);
+        self.along = Some(along);
+        let goal = world.map.ahead(along, LOOK_AHEAD);
+        let turn = vec_to_angle(goal - car.pos) - vec_to_angle(car.dir);
+        let turn = vec_to_angle(angle_to_vec(turn));
+
+        Input {
+            throttle: true,
+            steer: (turn * 4.0).clamp(-1.0, 1.0),
+            aim: goal,
+            fire: false,
+            reel: 0.0,
+        }
+    }
+}
+
+#[test]
+fn scripted_holds_each_input() {
+    use super::Map;
+    let world = World::new(Map::donut());
+    let gas = Input { throttle: true, ..Default::default() };
+    let fire = Input { fire: true, ..Default::default() };
+    let mut script = Scripted::new(vec![(2, gas), (0, gas), (1, fire)]);
+    let played: Vec<Input> = (0..5).map(|_| script.input(&world)).collect();
+    assert_eq!(played, vec![gas, gas, fire, Input::default(), Input::default()]);
+}
+
+#[test]
+fn bot_drives_around_the_track() {
+    use super::Map;
+    let mut world = World::new(Map::donut());
+    let mut bot = Bot::default();
+    let start = vec_to_angle(world.car.pos);
+    for _ in 0..60 * 20 {
+        let input = bot.input(&world);
+        world.step(input);
+        assert!(world.map.on_road(world.car.pos), "bot drove off the road at {:?}", world.car.pos);
+    }
+    assert!(vec_to_angle(world.car.pos) != start);
+}
@@ -6,78 +6,147 @@ mod math;
 mod car;
 use car::Car;
 mod circle;
-use circle::{Circle, Collision, CircleArena, ArenaKey};
+use circle::{Collider, Collision, CircleArena, ArenaKey, Layers, Material};
+mod shape;
+use shape::Shape;
 mod can;
 use can::{Can, Cantainer};
 mod hook;
-use hook::Hook;
+use hook::{Hook, Winch};
+mod chain;
+use chain::Chain;
+mod time;
+use time::{FixedTimestep, SimClock};
+mod input;
+use input::{Input, InputSource};
+mod world;
+use world::World;
+mod render;
+use render::{Renderer, Snapshot};
+mod replay;
+use replay::Replay;
+mod ghost;
+use ghost::Ghost;
+mod race;
+use race::Race;
+mod mode;
+use mode::Mode;
+mod clearing;
+mod delivery;
+mod slots;
+use slots::{Handle, Slots};
+mod surface;
+use surface::Surface;
 
-#[cfg(not(feature = "donutvision"))]
-const ZOOM: f32 = 8.0;
-#[cfg(feature = "donutvision")]
-const ZOOM: f32 = 55.0;
+/// The value following `--name` on the command line, if there is one.
+fn arg(name: &str) -> Option<String> {
+    let mut args = std::env::args();
+    args.find(|a| a == name)?;
+    args.next()
+}
+
+/// Whichever gamepad gets used, if the game was built to read them.
+#[cfg(feature = "gamepad")]
+fn gamepad() -> Box<dyn InputSource> {
+    Box::new(input::Gamepad::new().unwrap_or_else(|e| panic!("couldn't read gamepads: {}", e)))
+}
+#[cfg(not(feature = "gamepad"))]
+fn gamepad() -> Box<dyn InputSource> {
+    panic!("built without gamepad support, rebuild with `--features gamepad` for it")
+}
 
 #[macroquad::main("donuts")]
 async fn main() {
-    let mut arena = CircleArena::new();
-    let map = Map;
-    let mut car = Car {
-        pos: map.car_spawn(),
-        ..Car::new(load_texture("car.png").await)
+    let mut renderer = Renderer::load().await;
+    let map = match arg("--map") {
+        Some(path) => Map::load(&path).unwrap_or_else(|e| panic!("couldn't load map {}: {}", path, e)),
+        None => Map::donut(),
+    };
+    let map = match std::env::args().any(|a| a == "--walls") {
+        true => map.with_ring_walls(0.4),
+        false => map,
+    };
+    let replay = arg("--replay").map(|path| Replay::load(&path).expect("couldn't load replay"));
+    // a replay's played back in the mode it was recorded in, whatever's asked for
+    let kind = match &replay {
+        Some(replay) => replay.mode,
+        None if std::env::args().any(|a| a == "--clear") => mode::Kind::Clearing,
+        None if std::env::args().any(|a| a == "--deliver") => mode::Kind::Delivery,
+        None => mode::Kind::Free,
+    };
+    let start = || {
+        let mut world = World::new(map.clone());
+        world.mode = kind.start(&world);
+        world
+    };
+    let mut world = start();
+    let mut prev = Snapshot::of(&world);
+    let mut timestep = FixedTimestep::new();
+    let mut paused = false;
+    let mut source: Box<dyn InputSource> = if let Some(replay) = replay {
+        if replay.map != world.map.name() {
+            panic!("replay was recorded on {}, not {}", replay.map, world.map.name());
+        }
+        Box::new(replay.playback())
+    } else if std::env::args().any(|a| a == "--bot") {
+        Box::new(input::Bot::default())
+    } else if std::env::args().any(|a| a == "--gamepad") {
+        gamepad()
+    } else {
+        Box::new(input::KeyboardMouse)
     };
-    let mut hook = Hook::new();
-    let mut cans = Cantainer::new(map.can_spots().map(|pos| Can::new(pos)).collect());
+    let record = arg("--record");
+    let mut recording = Replay::new(world.map.name(), world.mode.kind());
+    let best_path = ghost::best_run_path(&world.map, kind);
+    let mut best = Replay::load(&best_path).ok().filter(|r| r.map == world.map.name() && r.mode == kind);
+    let mut ghost = best.as_ref().map(|r| Ghost::new(map.clone(), r));
+    let mut finished = false;
 
     loop {
-        clear_background(WHITE);
-
-        let cam = Camera2D {
-            rotation: car.angle(),
-            #[cfg(not(feature = "donutvision"))]
-            target: car.pos,
-            zoom: vec2(1.0, -screen_width() / screen_height()) / ZOOM,
-            ..Default::default()
-        };
-
-        match hook {
-            Hook::Ready { .. } => hook.face(car.dock(), cam.screen_to_world(mouse_position().into())),
-            Hook::Launched { .. } | Hook::Retracting { .. } => hook.fly(car.dock()),
-            Hook::Locked { can_index: i, .. } => hook.drag(car.dock(), &mut cans[i]),
+        if is_key_pressed(KeyCode::P) {
+            paused = !paused;
         }
-        car.controls(map.terrain_friction(car.pos));
-        for can in &mut *cans {
-            can.slide(map.terrain_friction(can.pos))
-        }
-        if is_mouse_button_down(MouseButton::Left) {
-            match hook {
-                Hook::Ready { .. } => hook.launch(car.dock()),
-                Hook::Locked { can_index: i, .. } => hook.release(&mut cans[i]),
-                _ => {},
-            }
+        let frame_time = if paused { 0.0 } else { get_frame_time() };
+
+        if is_key_pressed(KeyCode::R) {
+            let best_lap = world.race.best_lap.take();
+            world = start();
+            world.race.best_lap = best_lap;
+            prev = Snapshot::of(&world);
+            recording = Replay::new(world.map.name(), world.mode.kind());
+            ghost = best.as_ref().map(|r| Ghost::new(map.clone(), r));
+            finished = false;
         }
 
-        set_camera(cam);
-        map.draw();
-        car.draw();
-        hook.draw_hook(car.dock());
-        cans.draw();
-        hook.draw_chain(car.dock());
+        for _ in 0..timestep.advance(frame_time) {
+            prev = Snapshot::of(&world);
+            let input = source.input(&world);
+            recording.record(input);
+            world.step(input);
+            renderer.react(&world.events);
+            if let Some(ghost) = &mut ghost {
+                ghost.step();
+            }
 
-        #[cfg(feature = "showcollision")]
-        for c in car.circles().chain(cans.circles()).chain(hook.circles()) {
-            draw_circle_lines(c.pos.x(), c.pos.y(), c.radius, 0.1, RED);
+            if !finished && world.mode.won(&world) {
+                finished = true;
+                if !matches!(&best, Some(b) if b.steps() <= recording.steps()) {
+                    if let Err(e) = recording.save(&best_path) {
+                        eprintln!("couldn't save best run to {}: {}", best_path, e);
+                    }
+                    best = Some(recording.clone());
+                }
+            }
         }
 
-        arena.collide(car.circles().chain(cans.circles()).chain(hook.circles()));
-        for Collision { members, normal, depth, .. } in arena.collided() {
-            match members {
-                [ArenaKey::Hook, ArenaKey::Can(i)] => hook.lock(car.dock(), i, &mut cans[i]),
-                [ArenaKey::Can(i), ArenaKey::Hook] if cans[i].vel.length() < 0.5 => cans[i].knockback(normal * 0.1),
-                [ArenaKey::Can(i), _] => cans[i].knockback(normal * depth),
-                _ => {},
+        if let (Some(path), true) = (&record, is_key_pressed(KeyCode::F5)) {
+            if let Err(e) = recording.save(path) {
+                eprintln!("couldn't save replay to {}: {}", path, e);
             }
         }
 
+        renderer.draw(&world, &prev, ghost.as_ref(), timestep.alpha());
+
         next_frame().await
     }
 }
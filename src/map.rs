@@ -1,79 +1,851 @@
 use macroquad::prelude::*;
-use std::f32::consts::{TAU, FRAC_PI_2};
-use super::math::*;
+use std::f32::consts::TAU;
+use std::fmt;
+use super::{ArenaKey, Collider, Layers, Material, Shape, Surface, math::*};
 
-const TRACK_RADIUS: f32 = 35.0;
-const TRACK_WIDTH: f32 = 10.0;
 #[cfg(feature = "donutvision")]
 const ROAD_3DNESS: f32 = 1.0;
 #[cfg(not(feature = "donutvision"))]
 const ROAD_3DNESS: f32 = 0.175;
 
-pub struct Map;
+/// How far out from its line a Wall is solid, on either side.
+const WALL_THICKNESS: f32 = 0.25;
+/// How far along the road either way something being followed along it gets looked for,
+/// from where it was the step before.
+const FOLLOW_RANGE: f32 = 5.0;
+
+/// A solid barrier running through a series of points.
+#[derive(Clone)]
+pub struct Wall {
+    pub points: Vec<Vec2>,
+    /// Whether the last point joins back up to the first.
+    pub closed: bool,
+    /// How bouncy the wall is, from 0 to 1.
+    pub restitution: f32,
+}
+impl Wall {
+    /// A closed loop of points around a circle.
+    pub fn ring(center: Vec2, radius: f32, restitution: f32) -> Self {
+        const SEGMENTS: usize = 64;
+        Self {
+            points: (0..SEGMENTS)
+                .map(|i| center + angle_to_vec(i as f32 / SEGMENTS as f32 * TAU) * radius)
+                .collect(),
+            closed: true,
+            restitution,
+        }
+    }
+
+    pub fn material(&self) -> Material {
+        Material {
+            mass: f32::INFINITY,
+            restitution: self.restitution,
+            friction: 0.4,
+        }
+    }
+
+    /// Each straight stretch of the wall, as pairs of points.
+    pub fn segments(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let n = self.points.len();
+        let count = if self.closed { n } else { n.saturating_sub(1) };
+        (0..count).map(move |i| (self.points[i], self.points[(i + 1) % n]))
+    }
+
+    fn draw(&self) {
+        for (a, b) in self.segments() {
+            draw_line(a.x(), a.y(), b.x(), b.y(), WALL_THICKNESS * 2.0, DARKGRAY);
+            draw_circle(a.x(), a.y(), WALL_THICKNESS, DARKGRAY);
+        }
+    }
+}
+
+/// The shape of the road.
+#[derive(Clone)]
+pub enum Track {
+    /// A band of road `width` wide, running around the inside of a circle of `radius`.
+    Ring {
+        center: Vec2,
+        radius: f32,
+        width: f32,
+    },
+    /// A closed Catmull-Rom spline through each point, with how wide the road is there.
+    /// The road runs from each point to the next, and from the last back to the first.
+    Spline {
+        points: Vec<(Vec2, f32)>,
+    },
+}
+impl Track {
+    /// Samples along the middle of the road in the direction it runs, each with how wide the road is there.
+    fn centerline(&self) -> Vec<(Vec2, f32)> {
+        match self {
+            &Track::Ring { center, radius, width } => {
+                const SAMPLES: usize = 64;
+                (0..SAMPLES)
+                    .map(|i| (center + angle_to_vec(-(i as f32) / SAMPLES as f32 * TAU) * (radius - width/2.0), width))
+                    .collect()
+            }
+            Track::Spline { points } => {
+                const SAMPLES: usize = 16;
+                let n = points.len();
+                (0..n)
+                    .flat_map(|i| {
+                        // the points either side of this stretch, wrapping around the ends
+                        let at = |offset: usize| points[(i + offset) % n];
+                        let (p0, p1, p2, p3) = (at(n - 1), at(0), at(1), at(2));
+                        (0..SAMPLES).map(move |s| {
+                            let t = s as f32 / SAMPLES as f32;
+                            (catmull_rom(p0.0, p1.0, p2.0, p3.0, t), lerp(p1.1, p2.1, t))
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The point `t` of the way from `p1` to `p2` on a Catmull-Rom spline.
+fn catmull_rom(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    let (t2, t3) = (t * t, t * t * t);
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5
+}
+
+/// Where on the map a patch of ground is.
+#[derive(Clone)]
+pub enum Area {
+    Circle {
+        center: Vec2,
+        radius: f32,
+    },
+    Rect {
+        min: Vec2,
+        max: Vec2,
+    },
+}
+impl Area {
+    fn contains(&self, pos: Vec2) -> bool {
+        match *self {
+            Area::Circle { center, radius } => (pos - center).length() <= radius,
+            Area::Rect { min, max } => {
+                (min.x()..=max.x()).contains(&pos.x()) && (min.y()..=max.y()).contains(&pos.y())
+            }
+        }
+    }
+
+    fn draw(&self, color: Color) {
+        match *self {
+            Area::Circle { center, radius } => draw_circle(center.x(), center.y(), radius, color),
+            Area::Rect { min, max } => {
+                let size = max - min;
+                draw_rectangle(min.x(), min.y(), size.x(), size.y(), color);
+            }
+        }
+    }
+}
+
+/// A patch of ground painted with a different surface than the road or grass around it.
+#[derive(Clone)]
+pub struct Region {
+    pub area: Area,
+    pub surface: Surface,
+}
+
+/// A few arrows in a row, painted on the ground.
+#[derive(Clone)]
+pub struct Arrows {
+    pub pos: Vec2,
+    pub dir: Vec2,
+    pub count: usize,
+}
+impl Arrows {
+    fn draw(&self) {
+        const SIZE: f32 = 1.2;
+        let side = perp_vec(self.dir) * SIZE / 2.0;
+        for n in 0..self.count {
+            let base = self.pos + self.dir * n as f32 * 0.545;
+            draw_triangle(base - side, base + side, base + self.dir * SIZE, YELLOW);
+        }
+    }
+}
+
+/// A point along the middle of the road.
+#[derive(Copy, Clone)]
+struct Sample {
+    pos: Vec2,
+    width: f32,
+    /// How far along the road this is from the first sample.
+    distance: f32,
+}
+
+/// What's wrong with a map file, and which line it's wrong on.
+#[derive(Debug, PartialEq)]
+pub struct MapError {
+    pub line: usize,
+    pub message: String,
+}
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for MapError {}
+
+/// One line of a map file, split into its keyword and whatever follows it.
+struct Line<'a> {
+    number: usize,
+    keyword: &'a str,
+    args: Vec<&'a str>,
+}
+impl Line<'_> {
+    fn error(&self, message: impl Into<String>) -> MapError {
+        MapError { line: self.number, message: message.into() }
+    }
+
+    fn number(&self, arg: &str) -> Result<f32, MapError> {
+        arg.parse::<f32>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .ok_or_else(|| self.error(format!("`{}` isn't a number", arg)))
+    }
+
+    /// All of the line's arguments from `skip` on, which there must be exactly `count` of, as numbers.
+    fn numbers(&self, skip: usize, count: usize) -> Result<Vec<f32>, MapError> {
+        let args = self.args.get(skip..).unwrap_or(&[]);
+        if args.len() != count {
+            return Err(self.error(format!("`{}` takes {} numbers, not {}", self.keyword, count, args.len())));
+        }
+        args.iter().map(|arg| self.number(arg)).collect()
+    }
+
+    /// Something between 0 and 1, like a friction or restitution.
+    fn fraction(&self, arg: &str) -> Result<f32, MapError> {
+        let n = self.number(arg)?;
+        if (0.0..=1.0).contains(&n) {
+            Ok(n)
+        } else {
+            Err(self.error(format!("{} should be between 0 and 1", n)))
+        }
+    }
+
+    fn surface(&self, surfaces: &[(String, Surface)], name: &str) -> Result<Surface, MapError> {
+        surfaces.iter()
+            .find(|(existing, _)| existing == name)
+            .map(|&(_, surface)| surface)
+            .ok_or_else(|| self.error(format!("there's no surface called `{}`", name)))
+    }
+
+    fn arg(&self, i: usize, what: &str) -> Result<&str, MapError> {
+        self.args.get(i).copied().ok_or_else(|| self.error(format!("`{}` is missing its {}", self.keyword, what)))
+    }
+}
+
+#[derive(Clone)]
+pub struct Map {
+    name: String,
+    pub track: Track,
+    /// What the road is made of.
+    pub road: Surface,
+    /// What everything off the road is made of.
+    pub ground: Surface,
+    /// Later regions are laid over earlier ones.
+    pub regions: Vec<Region>,
+    spawn: (Vec2, Vec2),
+    /// Lines across the road that have to be crossed in order to finish a lap, the start line first.
+    gates: Vec<(Vec2, Vec2)>,
+    pub cans: Vec<Vec2>,
+    pub walls: Vec<Wall>,
+    pub arrows: Vec<Arrows>,
+    /// Places to drop cans off at, as a center and a radius.
+    pub zones: Vec<(Vec2, f32)>,
+    /// Posts for the hook to latch onto and swing the car around, as a center and a radius.
+    pub anchors: Vec<(Vec2, f32)>,
+    centerline: Vec<Sample>,
+    /// How far it is around the track.
+    length: f32,
+}
 impl Map {
+    /// The Trigger key of the line cars start behind.
+    pub const START_LINE: usize = 0;
+    pub const ANCHOR_MATERIAL: Material = Material {
+        mass: f32::INFINITY,
+        restitution: 0.3,
+        friction: 0.4,
+    };
+
+    /// The classic: one big ring of road, and nothing stopping you from driving off of it.
+    pub fn donut() -> Self {
+        Self::parse(include_str!("../maps/donut.map")).expect("maps/donut.map is broken")
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+        Self::parse(&std::fs::read_to_string(path)?).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Reads a map out of the text of a map file.
+    /// Each line is a keyword followed by its arguments, separated by spaces, and `#` starts a comment.
+    /// Angles are in degrees, colors run from 0 to 255, and these are all the keywords there are:
+    ///
+    /// - `name word`, once
+    /// - `ring x y radius width`, once, or else a `point x y width` for each point the road runs through, at least three
+    /// - `surface name grip drag steer can_drag red green blue`, adding a surface or replacing one of the same name
+    /// - `road surface` and `ground surface`, once each
+    /// - `region surface circle x y radius` or `region surface rect x y x y`
+    /// - `spawn x y heading`, once
+    /// - `start x y x y`, once
+    /// - `checkpoint x y x y` for each checkpoint in order, or else `checkpoints count`, once, to spread them out
+    /// - `can x y`, and `cans count`, once, to spread out more
+    /// - `wall bounciness circle x y radius`, or `wall bounciness line x y x y ...` and `loop` likewise
+    /// - `zone x y radius`
+    /// - `anchor x y radius`
+    /// - `arrows x y heading count`
+    pub fn parse(text: &str) -> Result<Self, MapError> {
+        let mut name = None;
+        let mut track = None;
+        let mut road = None;
+        let mut ground = None;
+        let mut surfaces: Vec<(String, Surface)> = Surface::BUILT_IN.iter().map(|&(name, s)| (name.to_string(), s)).collect();
+        let mut spawn = None;
+        let mut start = None;
+        let mut map = Map {
+            name: String::new(),
+            track: Track::Ring { center: Vec2::zero(), radius: 0.0, width: 0.0 },
+            road: Surface::ASPHALT,
+            ground: Surface::GRASS,
+            regions: vec![],
+            spawn: (Vec2::zero(), Vec2::unit_x()),
+            gates: vec![(Vec2::zero(), Vec2::zero())],
+            cans: vec![],
+            walls: vec![],
+            arrows: vec![],
+            zones: vec![],
+            anchors: vec![],
+            centerline: vec![],
+            length: 0.0,
+        };
+        let mut points = vec![];
+        let mut first_point = None;
+        let mut spread = None;
+        let mut spread_count = 0;
+        let mut checkpoints = None;
+        let mut checkpoint_count = 0;
+        let mut first_checkpoint = None;
+
+        for (i, text) in text.lines().enumerate() {
+            let mut words = text.split('#').next().unwrap_or("").split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let line = Line { number: i + 1, keyword, args: words.collect() };
+            let once = |seen: &Option<usize>| match seen {
+                Some(first) => Err(line.error(format!("`{}` was already given on line {}", keyword, first))),
+                None => Ok(Some(line.number)),
+            };
+
+            match keyword {
+                "name" => {
+                    name = once(&name)?;
+                    match line.args[..] {
+                        [word] => map.name = word.to_string(),
+                        _ => return Err(line.error("`name` takes one word")),
+                    }
+                }
+                "ring" => {
+                    track = once(&track)?;
+                    let n = line.numbers(0, 4)?;
+                    if n[3] <= 0.0 || n[3] >= n[2] {
+                        return Err(line.error("the road has to be wider than nothing, and narrower than the ring"));
+                    }
+                    map.track = Track::Ring { center: vec2(n[0], n[1]), radius: n[2], width: n[3] };
+                }
+                "point" => {
+                    first_point = first_point.or(Some(line.number));
+                    let n = line.numbers(0, 3)?;
+                    if n[2] <= 0.0 {
+                        return Err(line.error("the road has to be wider than nothing"));
+                    }
+                    points.push((vec2(n[0], n[1]), n[2]));
+                }
+                "surface" => {
+                    let name = line.arg(0, "name")?;
+                    let n = line.numbers(1, 7)?;
+                    if n[0] < 0.0 || n[2] < 0.0 {
+                        return Err(line.error("grip and steering can't be less than nothing"));
+                    }
+                    if n[1] >= 1.0 {
+                        return Err(line.error("drag has to leave the car some of its speed, so it has to be under 1"));
+                    }
+                    line.fraction(line.args[4])?;
+                    if n[4..].iter().any(|&c| !(0.0..=255.0).contains(&c)) {
+                        return Err(line.error("colors are red, green and blue, each from 0 to 255"));
+                    }
+                    let surface = Surface {
+                        grip: n[0],
+                        drag: n[1],
+                        steer: n[2],
+                        can_drag: n[3],
+                        color: Color::new(n[4] / 255.0, n[5] / 255.0, n[6] / 255.0, 1.0),
+                    };
+                    surfaces.retain(|(existing, _)| existing != name);
+                    surfaces.push((name.to_string(), surface));
+                }
+                "road" | "ground" => {
+                    let surface = match line.args[..] {
+                        [name] => line.surface(&surfaces, name)?,
+                        _ => return Err(line.error(format!("`{}` takes the name of a surface", keyword))),
+                    };
+                    if keyword == "road" {
+                        road = once(&road)?;
+                        map.road = surface;
+                    } else {
+                        ground = once(&ground)?;
+                        map.ground = surface;
+                    }
+                }
+                "region" => {
+                    let surface = line.surface(&surfaces, line.arg(0, "surface")?)?;
+                    let area = match line.arg(1, "shape")? {
+                        "circle" => {
+                            let n = line.numbers(2, 3)?;
+                            Area::Circle { center: vec2(n[0], n[1]), radius: n[2] }
+                        }
+                        "rect" => {
+                            let n = line.numbers(2, 4)?;
+                            let (a, b) = (vec2(n[0], n[1]), vec2(n[2], n[3]));
+                            Area::Rect { min: a.min(b), max: a.max(b) }
+                        }
+                        other => return Err(line.error(format!("regions are a `circle` or a `rect`, not `{}`", other))),
+                    };
+                    map.regions.push(Region { area, surface });
+                }
+                "spawn" => {
+                    spawn = once(&spawn)?;
+                    let n = line.numbers(0, 3)?;
+                    map.spawn = (vec2(n[0], n[1]), angle_to_vec(n[2].to_radians()));
+                }
+                "start" => {
+                    start = once(&start)?;
+                    let n = line.numbers(0, 4)?;
+                    map.gates[Self::START_LINE] = (vec2(n[0], n[1]), vec2(n[2], n[3]));
+                }
+                "checkpoint" => {
+                    first_checkpoint = first_checkpoint.or(Some(line.number));
+                    let n = line.numbers(0, 4)?;
+                    map.gates.push((vec2(n[0], n[1]), vec2(n[2], n[3])));
+                }
+                "checkpoints" => {
+                    let n = line.numbers(0, 1)?;
+                    if n[0] < 1.0 || n[0].fract() != 0.0 {
+                        return Err(line.error("there has to be a whole number of checkpoints, and at least one"));
+                    }
+                    checkpoints = once(&checkpoints)?;
+                    checkpoint_count = n[0] as usize;
+                }
+                "cans" => {
+                    let n = line.numbers(0, 1)?;
+                    if n[0] < 1.0 || n[0].fract() != 0.0 {
+                        return Err(line.error("there has to be a whole number of cans, and at least one"));
+                    }
+                    spread = once(&spread)?;
+                    spread_count = n[0] as usize;
+                }
+                "can" => {
+                    let n = line.numbers(0, 2)?;
+                    map.cans.push(vec2(n[0], n[1]));
+                }
+                "wall" => {
+                    let restitution = line.fraction(line.arg(0, "bounciness")?)?;
+                    let wall = match line.arg(1, "shape")? {
+                        "circle" => {
+                            let n = line.numbers(2, 3)?;
+                            Wall::ring(vec2(n[0], n[1]), n[2], restitution)
+                        }
+                        kind @ "loop" | kind @ "line" => {
+                            let n = line.args[2..].iter().map(|arg| line.number(arg)).collect::<Result<Vec<_>, _>>()?;
+                            if n.len() % 2 != 0 || n.len() < 4 {
+                                return Err(line.error("walls need at least two points, each an x and a y"));
+                            }
+                            Wall {
+                                points: n.chunks(2).map(|p| vec2(p[0], p[1])).collect(),
+                                closed: kind == "loop",
+                                restitution,
+                            }
+                        }
+                        other => return Err(line.error(format!("walls are a `circle`, `loop` or `line`, not `{}`", other))),
+                    };
+                    map.walls.push(wall);
+                }
+                "zone" => {
+                    let n = line.numbers(0, 3)?;
+                    if n[2] <= 0.0 {
+                        return Err(line.error("a zone has to be bigger than nothing"));
+                    }
+                    map.zones.push((vec2(n[0], n[1]), n[2]));
+                }
+                "anchor" => {
+                    let n = line.numbers(0, 3)?;
+                    if n[2] <= 0.0 {
+                        return Err(line.error("an anchor has to be bigger than nothing"));
+                    }
+                    map.anchors.push((vec2(n[0], n[1]), n[2]));
+                }
+                "arrows" => {
+                    let n = line.numbers(0, 4)?;
+                    if n[3] < 1.0 || n[3].fract() != 0.0 {
+                        return Err(line.error("there has to be a whole number of arrows, and at least one"));
+                    }
+                    map.arrows.push(Arrows {
+                        pos: vec2(n[0], n[1]),
+                        dir: angle_to_vec(n[2].to_radians()),
+                        count: n[3] as usize,
+                    });
+                }
+                other => return Err(line.error(format!("`{}` isn't something a map can have", other))),
+            }
+        }
+
+        let end = text.lines().count();
+        if let Some(line) = first_point {
+            if track.is_some() {
+                return Err(MapError { line, message: "a track is either a `ring` or `point`s, not both".to_string() });
+            }
+            if points.len() < 3 {
+                return Err(MapError { line, message: "a track needs at least three `point`s to go around".to_string() });
+            }
+            map.track = Track::Spline { points };
+            track = first_point;
+        }
+        // the spread out ones would all come after the placed ones, whatever order the lines were in
+        if let (Some(line), Some(_)) = (checkpoints, first_checkpoint) {
+            return Err(MapError { line, message: "checkpoints are either placed with `checkpoint` or spread out with `checkpoints`, not both".to_string() });
+        }
+        for (seen, keyword) in [(name, "name"), (track, "ring"), (spawn, "spawn"), (start, "start")].iter() {
+            if seen.is_none() {
+                return Err(MapError { line: end, message: format!("the map never gives its `{}`", keyword) });
+            }
+        }
+
+        map.measure();
+        if map.length <= 0.0 {
+            return Err(MapError { line: track.unwrap_or(end), message: "the track has to go somewhere, not stay in one spot".to_string() });
+        }
+        if let Track::Spline { .. } = map.track {
+            if map.arrows.is_empty() {
+                for i in 0..4 {
+                    let (pos, dir, _) = map.along(map.length * i as f32 / 4.0);
+                    map.arrows.push(Arrows { pos, dir, count: i + 1 });
+                }
+            }
+        }
+        // evenly spaced around the track from the start line, right across the road
+        let (a, b) = map.gates[Self::START_LINE];
+        let start = map.nearest((a + b) / 2.0, None).0;
+        for i in 1..=checkpoint_count {
+            let (pos, dir, width) = map.along(start + map.length * i as f32 / (checkpoint_count + 1) as f32);
+            let side = perp_vec(dir) * width * 0.6;
+            map.gates.push((pos + side, pos - side));
+        }
+        // staggered from one side of the road to the other
+        for i in 0..spread_count {
+            let (pos, dir, width) = map.along(map.length * i as f32 / spread_count as f32);
+            map.cans.push(pos + perp_vec(dir) * width * [0.35, -0.3][i % 2]);
+        }
+        Ok(map)
+    }
+
+    /// Lays out samples along the middle of the track, for everything that needs to follow it.
+    fn measure(&mut self) {
+        let points = self.track.centerline();
+        let mut distance = 0.0;
+        self.centerline = points.iter()
+            .enumerate()
+            .map(|(i, &(pos, width))| {
+                if i > 0 {
+                    distance += (pos - points[i - 1].0).length();
+                }
+                Sample { pos, width, distance }
+            })
+            .collect();
+        self.length = distance + (points[0].0 - points[points.len() - 1].0).length();
+    }
+
+    /// The middle of the road `distance` along it from where the track starts,
+    /// which way the road runs there, and how wide it is.
+    fn along(&self, distance: f32) -> (Vec2, Vec2, f32) {
+        let n = self.centerline.len();
+        let distance = distance.rem_euclid(self.length);
+        let i = self.centerline.iter().rposition(|s| s.distance <= distance).unwrap_or(0);
+        let (a, b) = (self.centerline[i], self.centerline[(i + 1) % n]);
+        let end = if i + 1 == n { self.length } else { b.distance };
+        let t = if end > a.distance { (distance - a.distance) / (end - a.distance) } else { 0.0 };
+        (a.pos.lerp(b.pos, t), (b.pos - a.pos).normalize(), lerp(a.width, b.width, t))
+    }
+
+    /// How far along the road the nearest point on its middle to `pos` is,
+    /// how far `pos` is from there, and how wide the road is there.
+    /// With a distance `near` to look around, only the road within `FOLLOW_RANGE` of that far along it counts.
+    fn nearest(&self, pos: Vec2, near: Option<f32>) -> (f32, f32, f32) {
+        let n = self.centerline.len();
+        let length = self.length;
+        let in_range = |i: usize| match near {
+            Some(near) => {
+                let start = self.centerline[i].distance;
+                let end = if i + 1 == n { length } else { self.centerline[i + 1].distance };
+                // how far ahead of `near` the stretch starts, the short way round
+                let ahead = (start - near + length / 2.0).rem_euclid(length) - length / 2.0;
+                ahead <= FOLLOW_RANGE && ahead + (end - start) >= -FOLLOW_RANGE
+            }
+            None => true,
+        };
+        (0..n)
+            .filter(|&i| in_range(i))
+            .map(|i| {
+                let (a, b) = (self.centerline[i], self.centerline[(i + 1) % n]);
+                let along = b.pos - a.pos;
+                let t = ((pos - a.pos).dot(along) / along.length_squared()).clamp(0.0, 1.0);
+                let closest = a.pos + along * t;
+                (a.distance + along.length() * t, (pos - closest).length(), lerp(a.width, b.width, t))
+            })
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap()
+    }
+
+    /// Walls both edges of the road, with the given bounciness.
+    /// Only ring tracks get them, since the edges of other tracks can cross each other.
+    pub fn with_ring_walls(mut self, restitution: f32) -> Self {
+        let (center, radius, width) = match self.track {
+            Track::Ring { center, radius, width } => (center, radius, width),
+            Track::Spline { .. } => return self,
+        };
+        self.name = format!("{}-walled", self.name);
+        self.walls.push(Wall::ring(center, radius + WALL_THICKNESS, restitution));
+        self.walls.push(Wall::ring(center, radius - width - WALL_THICKNESS, restitution));
+        self
+    }
+
+    /// What replays and best runs on this map are filed under.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn draw(&self) {
         self.track();
+        for region in &self.regions {
+            region.area.draw(region.surface.color);
+        }
         self.lines();
-        self.arrows();
+        let (a, b) = self.gates[Self::START_LINE];
+        draw_line(a.x(), a.y(), b.x(), b.y(), 0.3, WHITE);
+        for arrows in &self.arrows {
+            arrows.draw();
+        }
+        for &(pos, radius) in &self.zones {
+            draw_circle_lines(pos.x(), pos.y(), radius, 0.15, DARKGREEN);
+        }
+        for wall in &self.walls {
+            wall.draw();
+        }
+        for &(pos, radius) in &self.anchors {
+            draw_circle(pos.x(), pos.y(), radius, DARKGRAY);
+            draw_circle(pos.x(), pos.y() - ROAD_3DNESS * 0.5, radius * 0.7, GRAY);
+        }
     }
     
     fn track(&self) {
-        draw_circle(0.0, -ROAD_3DNESS, TRACK_RADIUS, DARKGRAY);
-        draw_circle(0.0,  0.0, TRACK_RADIUS, GRAY);
-        draw_circle(0.0,  0.0, TRACK_RADIUS - TRACK_WIDTH, DARKGRAY);
-        draw_circle(0.0, -ROAD_3DNESS, TRACK_RADIUS - TRACK_WIDTH, WHITE);
+        match self.track {
+            Track::Ring { center: c, radius, width } => {
+                draw_circle(c.x(), c.y() - ROAD_3DNESS, radius, self.road.shade());
+                draw_circle(c.x(), c.y(), radius, self.road.color);
+                draw_circle(c.x(), c.y(), radius - width, self.road.shade());
+                draw_circle(c.x(), c.y() - ROAD_3DNESS, radius - width, self.ground.color);
+            }
+            Track::Spline { .. } => {
+                let n = self.centerline.len();
+                let edges: Vec<(Vec2, Vec2)> = (0..n)
+                    .map(|i| {
+                        let Sample { pos, width, .. } = self.centerline[i];
+                        let dir = self.centerline[(i + 1) % n].pos - self.centerline[(i + n - 1) % n].pos;
+                        let side = perp_vec(dir.normalize()) * width / 2.0;
+                        (pos + side, pos - side)
+                    })
+                    .collect();
+                // the side of the road first, then the top of it
+                for &(drop, color) in &[(vec2(0.0, -ROAD_3DNESS), self.road.shade()), (Vec2::zero(), self.road.color)] {
+                    for i in 0..n {
+                        let ((a, b), (c, d)) = (edges[i], edges[(i + 1) % n]);
+                        draw_triangle(a + drop, b + drop, c + drop, color);
+                        draw_triangle(b + drop, d + drop, c + drop, color);
+                    }
+                }
+            }
+        }
     }
 
     pub fn lines(&self) {
-        const MAX: usize = 50;
-        for i in 0..MAX {
-            let f = i as f32 / MAX as f32;
-            let width = TRACK_RADIUS - TRACK_WIDTH/2.0;
-            let (x, y) = (angle_to_vec(f * TAU) * width).into();
-            let (w, z) = (angle_to_vec((f + 0.01) * TAU) * width).into();
-            draw_line(x, y, w, z, 0.2, YELLOW);
+        const SPACING: f32 = 3.77;
+        let count = (self.length / SPACING).round().max(1.0) as usize;
+        let spacing = self.length / count as f32;
+        for i in 0..count {
+            let (start, ..) = self.along(i as f32 * spacing);
+            let (end, ..) = self.along((i as f32 + 0.5) * spacing);
+            draw_line(start.x(), start.y(), end.x(), end.y(), 0.2, YELLOW);
         }
     }
 
-    fn arrows(&self) {
-        let v = self.car_spawn().y();
-        const MAX: usize = 4;
-        const SIZE: f32 = 1.2;
-        for i in 0..MAX {
-            let a = -(i as f32 / MAX as f32) * TAU;
-            let p = angle_to_vec(a + FRAC_PI_2);
-            let tip = angle_to_vec(a);
-            for n in 0..(i+1) {
-                let arrow_offset = tip * n as f32 * 0.545;
-                draw_triangle(
-                    p * (v - SIZE/2.0) + arrow_offset,
-                    p * (v + SIZE/2.0) + arrow_offset,
-                    p * v + tip * SIZE + arrow_offset,
-                    YELLOW
-                );
-            }
+    /// Where the car starts, and which way it's pointing.
+    pub fn car_spawn(&self) -> (Vec2, Vec2) {
+        self.spawn
+    }
+
+    /// How many lines a lap has to cross, counting the start line.
+    pub fn gate_count(&self) -> usize {
+        self.gates.len()
+    }
+
+    /// Sensors that report whatever enters and leaves them.
+    /// The start line and checkpoints are keyed by the order they're crossed in.
+    pub fn triggers(&self) -> impl Iterator<Item = Collider> + '_ {
+        self.gates.iter().enumerate().map(|(i, &(a, b))| Collider {
+            shape: Shape::Segment { a, b },
+            key: ArenaKey::Trigger(i),
+            layer: Layers::SENSOR,
+            mask: Layers::CAR | Layers::CAN,
+        })
+    }
+
+    /// Sensors over each drop-off zone, that notice cans coming and going.
+    pub fn zone_colliders(&self) -> impl Iterator<Item = Collider> + '_ {
+        self.zones.iter().enumerate().map(|(i, &(pos, radius))| Collider {
+            shape: Shape::Circle { pos, radius },
+            key: ArenaKey::Zone(i),
+            layer: Layers::SENSOR,
+            mask: Layers::CAN,
+        })
+    }
+
+    /// Posts that stop the car and cans, and that the hook can latch onto.
+    pub fn anchor_colliders(&self) -> impl Iterator<Item = Collider> + '_ {
+        self.anchors.iter().enumerate().map(|(i, &(pos, radius))| Collider {
+            shape: Shape::Circle { pos, radius },
+            key: ArenaKey::Anchor(i),
+            layer: Layers::WALL | Layers::GRABBABLE,
+            mask: Layers::CAR | Layers::CAN | Layers::HOOK,
+        })
+    }
+
+    /// How far along the road `pos` is, given how far along it was the step before, if it's been followed before.
+    /// Following on from there keeps to the same stretch of road where the track crosses over itself,
+    /// unless `pos` has ended up off that stretch altogether, when it's on whichever's nearest.
+    pub fn follow(&self, pos: Vec2, was: Option<f32>) -> f32 {
+        match was.map(|was| self.nearest(pos, Some(was))) {
+            Some((along, off_middle, width)) if off_middle <= width / 2.0 => along,
+            _ => self.nearest(pos, None).0,
         }
     }
 
-    pub fn car_spawn(&self) -> Vec2 {
-        vec2(0.0, TRACK_RADIUS - TRACK_WIDTH/4.0)
+    /// Which way the road runs `distance` along it.
+    pub fn heading(&self, distance: f32) -> Vec2 {
+        self.along(distance).1
     }
 
-    pub fn can_spots(&self) -> impl Iterator<Item = Vec2> {
-        const MAX: usize = 20;
-        (0..MAX).map(|i| {
-            angle_to_vec((i as f32 / MAX as f32) * TAU)
-                * (TRACK_RADIUS - (TRACK_WIDTH * [-0.35, 0.3][i % 2]) - (TRACK_WIDTH / 2.0))
+    /// Every corner of every wall, and the middle of every anchor post, for the hook's chain to catch on.
+    pub fn corners(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.walls.iter().flat_map(|wall| wall.points.iter().copied())
+            .chain(self.anchors.iter().map(|&(pos, _)| pos))
+    }
+
+    pub fn wall_colliders(&self) -> impl Iterator<Item = Collider> + '_ {
+        self.walls.iter().enumerate().flat_map(|(i, wall)| {
+            wall.segments().map(move |(a, b)| Collider {
+                shape: Shape::Capsule { a, b, radius: WALL_THICKNESS },
+                key: ArenaKey::Wall(i),
+                layer: Layers::WALL,
+                mask: Layers::CAR | Layers::CAN,
+            })
         })
     }
 
-    pub fn terrain_friction(&self, pos: Vec2) -> f32 {
-        if pos.length() < TRACK_RADIUS - TRACK_WIDTH {
-            0.98
-        } else if pos.length() > TRACK_RADIUS {
-            0.98
-        } else {
-            0.96
+    /// A point on the middle of the track, `distance` further along it than `from` in the direction of the arrows.
+    pub fn ahead(&self, from: f32, distance: f32) -> Vec2 {
+        self.along(from + distance).0
+    }
+
+    pub fn can_spots(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.cans.iter().copied()
+    }
+
+    pub fn on_road(&self, pos: Vec2) -> bool {
+        match self.track {
+            Track::Ring { center, radius, width } => {
+                let dist = (pos - center).length();
+                (radius - width..=radius).contains(&dist)
+            }
+            Track::Spline { .. } => {
+                let (_, off_middle, width) = self.nearest(pos, None);
+                off_middle <= width / 2.0
+            }
         }
     }
+
+    /// What the ground at `pos` is made of.
+    pub fn surface(&self, pos: Vec2) -> &Surface {
+        match self.regions.iter().rev().find(|region| region.area.contains(pos)) {
+            Some(region) => &region.surface,
+            None if self.on_road(pos) => &self.road,
+            None => &self.ground,
+        }
+    }
+}
+
+#[test]
+fn map_errors_point_at_their_line() {
+    let error = |text: &str| Map::parse(text).err().map(|e| (e.line, e.message));
+    let donut = include_str!("../maps/donut.map");
+    assert!(Map::parse(donut).is_ok());
+
+    let line_of = |text: &str| donut.lines().position(|line| line == text).map(|i| i + 1);
+    assert_eq!(error(&donut.replace("ring 0 0 35 10", "ring 0 0 35")).map(|e| e.0), line_of("ring 0 0 35 10"));
+    assert_eq!(error(&donut.replace("can 0 27", "can 0 twenty-seven")).map(|e| e.0), line_of("can 0 27"));
+    assert_eq!(error(&donut.replace("road asphalt", "road lava")).map(|e| e.0), line_of("road asphalt"));
+    assert_eq!(error(&format!("{}\nspawn 0 0 0", donut)).map(|e| e.0), Some(donut.lines().count() + 2));
+    assert_eq!(error(&donut.replace("start 0 35 0 25", "")).map(|e| e.0), Some(donut.lines().count()));
+    assert_eq!(error(&format!("{}\ncheckpoint 0 -25 0 -35", donut)).map(|e| e.0), line_of("checkpoints 3"));
+    let spot = "name a\npoint 5 5 10\npoint 5 5 10\npoint 5 5 10\nspawn 5 5 0\nstart 0 5 10 5";
+    assert_eq!(error(spot).map(|e| e.0), Some(2));
+    for bad in &["-1 0.1 1 0.1", "1 1 1 0.1", "1 0.1 -1 0.1", "1 0.1 1 1.5", "1 0.1 1 -0.1"] {
+        let text = format!("{}\nsurface goo {} 0 0 0", donut, bad);
+        assert_eq!(error(&text).map(|e| e.0), Some(donut.lines().count() + 2), "surface goo {}", bad);
+    }
+    assert_eq!(error("name a\nbumpers 1 2"), Some((2, "`bumpers` isn't something a map can have".to_string())));
+}
+
+#[test]
+fn regions_override_the_ground_under_them() {
+    let extra = "region ice circle 0 30 2\nsurface sand 0.5 0.2 0.6 0.2 230 210 160\nregion sand rect 0 0 -1 -1";
+    let map = Map::parse(&format!("{}\n{}", include_str!("../maps/donut.map"), extra)).unwrap();
+    assert_eq!(map.surface(vec2(0.0, 31.0)), &Surface::ICE);
+    assert_eq!(map.surface(vec2(0.0, 33.0)), &Surface::ASPHALT);
+    assert_eq!(map.surface(vec2(-0.5, -0.5)).drag, 0.2);
+    assert_eq!(map.surface(vec2(0.0, 10.0)), &Surface::GRASS);
+    assert_eq!(map.cans.len(), 20);
+}
+
+#[test]
+fn spline_tracks_follow_their_points() {
+    let map = Map::parse(include_str!("../maps/figure-eight.map")).unwrap();
+    assert_eq!(map.cans.len(), 24);
+    assert!(map.cans.iter().all(|&can| map.on_road(can)));
+    for &pos in &[vec2(40.0, 0.0), vec2(0.0, 0.0), vec2(-20.0, 17.32)] {
+        assert!(map.on_road(pos), "{:?} should be on the road", pos);
+    }
+    assert!(!map.on_road(vec2(0.0, 15.0)));
+    assert!(!map.on_road(vec2(30.0, 0.0)));
+
+    // the road runs from (40, 0) down towards (34.64, -17.32)
+    let ahead = map.ahead(map.follow(vec2(40.0, 0.0), None), 5.0);
+    assert!(ahead.y() < -4.0 && ahead.y() > -6.0, "{:?}", ahead);
+    assert_eq!(map.arrows.len(), 4);
 }
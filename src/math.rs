@@ -55,7 +55,7 @@ fn vec_to_angle_to_vec() {
 }
 
 pub fn slerp(q1: Vec2, q0: Vec2, t: f32) -> Vec2 {
-    const MU: f32 = 1.85298109240830;
+    const MU: f32 = 1.852_981_1;
     const U: [f32; 8] = [
         1.0 / (1.0 * 3.0),
         1.0 / (2.0 * 5.0),
@@ -121,3 +121,14 @@ pub fn slerp(q1: Vec2, q0: Vec2, t: f32) -> Vec2 {
 
     q0 * f0 + q1 * f1
 }
+
+/// `v` turned a quarter turn counter-clockwise.
+pub fn perp_vec(v: Vec2) -> Vec2 {
+    vec2(-v.y(), v.x())
+}
+
+/// The z component of the cross product of `a` and `b`, were they 3D.
+/// Positive when `b` is counter-clockwise from `a`.
+pub fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x() * b.y() - a.y() * b.x()
+}
@@ -0,0 +1,121 @@
+use super::{World, clearing::Clearing, delivery::{Delivery, Outcome}};
+
+/// What the player is trying to do, beyond driving around.
+#[derive(Clone)]
+pub enum Mode {
+    /// No goal but going fast; laps are still timed.
+    Free,
+    Clearing(Clearing),
+    Delivery(Delivery),
+}
+impl Mode {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Mode::Free => Kind::Free,
+            Mode::Clearing(_) => Kind::Clearing,
+            Mode::Delivery(_) => Kind::Delivery,
+        }
+    }
+
+    /// Catches up on the World's latest step.
+    pub fn update(&mut self, world: &mut World) {
+        match self {
+            Mode::Free => {},
+            Mode::Clearing(clearing) => clearing.update(world),
+            Mode::Delivery(delivery) => delivery.update(world),
+        }
+    }
+
+    /// Whether the run's been won outright, which is what the quickest runs are timed to.
+    pub fn won(&self, world: &World) -> bool {
+        match self {
+            // with no goal of its own, a run's over once every can's off the road
+            Mode::Free => world.cleared(),
+            Mode::Clearing(clearing) => matches!(clearing.round().results(), Some(r) if r.cleared == r.total),
+            Mode::Delivery(delivery) => matches!(delivery.round().results(), Some(r) if matches!(r.outcome, Outcome::AllDelivered | Outcome::OutOfZones)),
+        }
+    }
+}
+
+/// Which Mode a run is played in, without how far along it's got.
+/// Replays store it by number, so the numbers stay put.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Kind {
+    Free = 0,
+    Clearing = 1,
+    Delivery = 2,
+}
+impl Kind {
+    /// The mode from the very start, for `world` to be played in.
+    pub fn start(self, world: &World) -> Mode {
+        match self {
+            Kind::Free => Mode::Free,
+            Kind::Clearing => Mode::Clearing(Clearing::new(world)),
+            Kind::Delivery => Mode::Delivery(Delivery::new(world)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Free => "free",
+            Kind::Clearing => "clearing",
+            Kind::Delivery => "delivery",
+        }
+    }
+}
+
+/// Slower than this and a can that was let go of has come to rest.
+pub const SETTLED: f32 = 0.005;
+
+/// What every timed mode keeps track of: the score so far, when time runs out, and how it went once it's over.
+#[derive(Clone)]
+pub struct Round<R> {
+    score: f32,
+    deadline: f64,
+    results: Option<R>,
+}
+impl<R> Round<R> {
+    pub fn new(deadline: f64) -> Self {
+        Self {
+            score: 0.0,
+            deadline,
+            results: None,
+        }
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score as u32
+    }
+
+    /// Adds `points` to the score, or takes them away if there's a minus on them, though it never drops below nothing.
+    pub fn add(&mut self, points: f32) {
+        self.score = (self.score + points).max(0.0);
+    }
+
+    pub fn deadline(&self) -> f64 {
+        self.deadline
+    }
+
+    /// Restarts the clock, with time running out at `deadline`.
+    pub fn extend(&mut self, deadline: f64) {
+        self.deadline = deadline;
+    }
+
+    pub fn time_left(&self, now: f64) -> f64 {
+        (self.deadline - now).max(0.0)
+    }
+
+    pub fn over(&self) -> bool {
+        self.results.is_some()
+    }
+
+    /// Calls time on the round, with `results` to show for it.
+    pub fn end(&mut self, results: R) {
+        self.results = Some(results);
+    }
+
+    /// How the round went, once it's over.
+    pub fn results(&self) -> Option<&R> {
+        self.results.as_ref()
+    }
+}
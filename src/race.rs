@@ -0,0 +1,123 @@
+use super::{ArenaKey, Car, Map, circle::{Overlap, TriggerEvent}, world::Event};
+
+/// Slower than this and the car isn't really going any way at all, right or wrong.
+const MIN_SPEED: f32 = 0.02;
+
+/// A lap that was driven all the way around.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Lap {
+    pub time: f64,
+    /// How long each stretch between one gate and the next took, the last ending back at the start line.
+    pub splits: Vec<f64>,
+}
+
+/// Keeps track of the car's progress around the track from the gates it crosses:
+/// the start line, then each checkpoint in order, then the start line again.
+/// A lap only counts if every gate was crossed, and crossed in order, so cutting across the grass doesn't pay.
+#[derive(Clone)]
+pub struct Race {
+    gates: usize,
+    /// The gate the car has to cross next for its lap to count.
+    next: usize,
+    /// When the current lap started, if the car has crossed the start line yet.
+    lap_start: Option<f64>,
+    /// When the car crossed the last gate.
+    split_start: f64,
+    /// The splits driven so far this lap.
+    pub splits: Vec<f64>,
+    /// How many laps have been finished.
+    pub laps: usize,
+    pub last_lap: Option<Lap>,
+    pub best_lap: Option<Lap>,
+    /// Whether the car is driving against the way the road runs.
+    pub wrong_way: bool,
+    /// How far along the road the car was as of the last step, once there's been one.
+    along: Option<f32>,
+}
+impl Race {
+    pub fn new(map: &Map) -> Self {
+        Self {
+            gates: map.gate_count(),
+            next: Map::START_LINE,
+            lap_start: None,
+            split_start: 0.0,
+            splits: vec![],
+            laps: 0,
+            last_lap: None,
+            best_lap: None,
+            wrong_way: false,
+            along: None,
+        }
+    }
+
+    /// Catches up on whatever happened during the World's latest step, which happened at `now`.
+    pub fn update(&mut self, events: &[Event], now: f64, car: &Car, map: &Map) {
+        for event in events {
+            if let Event::Trigger(TriggerEvent { trigger: ArenaKey::Trigger(gate), other: ArenaKey::Car, overlap: Overlap::Enter }) = *event {
+                self.cross(gate, now);
+            }
+        }
+
+        let along = map.follow(car.pos, self.along);
+        self.along = Some(along);
+        let vel = car.velocity();
+        self.wrong_way = vel.length() > MIN_SPEED && vel.dot(map.heading(along)) < 0.0;
+    }
+
+    fn cross(&mut self, gate: usize, now: f64) {
+        // skipping ahead or doubling back doesn't get you anywhere
+        if gate != self.next {
+            return;
+        }
+        if let Some(start) = self.lap_start {
+            self.splits.push(now - self.split_start);
+            if gate == Map::START_LINE {
+                let lap = Lap { time: now - start, splits: std::mem::take(&mut self.splits) };
+                self.laps += 1;
+                if !matches!(&self.best_lap, Some(best) if best.time <= lap.time) {
+                    self.best_lap = Some(lap.clone());
+                }
+                self.last_lap = Some(lap);
+            }
+        }
+        if gate == Map::START_LINE {
+            self.lap_start = Some(now);
+        }
+        self.split_start = now;
+        self.next = (gate + 1) % self.gates;
+    }
+
+    /// The gate the car has to cross next, and how many there are in all.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, self.gates)
+    }
+
+    /// How long the current lap has been going as of `now`, if one has started.
+    pub fn lap_time(&self, now: f64) -> Option<f64> {
+        self.lap_start.map(|start| now - start)
+    }
+}
+
+#[test]
+fn laps_only_count_gates_crossed_in_order() {
+    let map = Map::donut();
+    let car = Car::new();
+    let mut race = Race::new(&map);
+    let mut cross = |gates: &[usize], times: &[f64]| {
+        for (&gate, &now) in gates.iter().zip(times) {
+            let event = Event::Trigger(TriggerEvent { trigger: ArenaKey::Trigger(gate), other: ArenaKey::Car, overlap: Overlap::Enter });
+            race.update(&[event], now, &car, &map);
+        }
+        race.clone()
+    };
+
+    // a shortcut from the start line straight back to it, then a proper lap
+    let race = cross(&[0, 2, 0, 1, 2, 3, 0], &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    assert_eq!(race.laps, 1);
+    assert_eq!(race.last_lap, Some(Lap { time: 6.0, splits: vec![3.0, 1.0, 1.0, 1.0] }));
+
+    let race = cross(&[1, 2, 3, 0], &[7.0, 8.0, 9.0, 10.0]);
+    assert_eq!(race.laps, 2);
+    assert_eq!(race.best_lap.as_ref().map(|lap| lap.time), Some(4.0));
+    assert_eq!(race.progress(), (1, 4));
+}
@@ -0,0 +1,280 @@
+use macroquad::prelude::*;
+use super::{ArenaKey, Car, Cantainer, Chain, Ghost, Hook, Mode, World, delivery::Outcome, world::Event, math::*};
+
+#[cfg(not(feature = "donutvision"))]
+const ZOOM: f32 = 8.0;
+#[cfg(feature = "donutvision")]
+const ZOOM: f32 = 55.0;
+const GHOST: Color = Color::new(1.0, 1.0, 1.0, 0.35);
+const GHOST_CHAIN: Color = Color::new(0.78, 0.78, 0.78, 0.35);
+const AIM: Color = Color::new(0.5, 0.5, 0.5, 0.4);
+
+/// The parts of a World that move, as they were before its latest step.
+/// Drawing blends between this and the World so motion stays smooth between steps.
+pub struct Snapshot {
+    car: Car,
+    hook: Hook,
+    chain: Chain,
+    cans: Cantainer,
+}
+impl Snapshot {
+    pub fn of(world: &World) -> Self {
+        Self {
+            car: world.car.clone(),
+            hook: world.hook.clone(),
+            chain: world.chain.clone(),
+            cans: world.cans.clone(),
+        }
+    }
+}
+
+pub fn camera(car: &Car) -> Camera2D {
+    Camera2D {
+        rotation: car.angle(),
+        #[cfg(not(feature = "donutvision"))]
+        target: car.pos,
+        zoom: vec2(1.0, -screen_width() / screen_height()) / ZOOM,
+        ..Default::default()
+    }
+}
+
+/// A bit of hot metal flung off the car scraping along a wall.
+struct Spark {
+    pos: Vec2,
+    vel: Vec2,
+    /// Seconds until it burns out.
+    life: f32,
+}
+
+/// Draws a World, holding onto whatever it needs from the GPU to do so,
+/// along with effects that only exist to be looked at.
+pub struct Renderer {
+    car_tex: Texture2D,
+    sparks: Vec<Spark>,
+    /// Feeds the made-up randomness sparks fly off with.
+    spark_seed: u32,
+}
+impl Renderer {
+    pub async fn load() -> Self {
+        let car_tex = load_texture("car.png").await;
+        set_texture_filter(car_tex, FilterMode::Nearest);
+        Self {
+            car_tex,
+            sparks: vec![],
+            spark_seed: 1,
+        }
+    }
+
+    /// Kicks off effects for whatever happened during the World's latest step.
+    pub fn react(&mut self, events: &[Event]) {
+        for event in events {
+            if let &Event::Scrape { pos, speed } = event {
+                self.spark_seed = self.spark_seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let spread = (self.spark_seed >> 16) as f32 / u16::MAX as f32 - 0.5;
+                self.sparks.push(Spark {
+                    pos,
+                    vel: angle_to_vec(spread * std::f32::consts::TAU) * speed * 40.0,
+                    life: 0.3,
+                });
+            }
+        }
+    }
+
+    fn draw_sparks(&mut self) {
+        let dt = get_frame_time();
+        self.sparks.retain(|spark| spark.life > 0.0);
+        for spark in &mut self.sparks {
+            spark.life -= dt;
+            spark.pos += spark.vel * dt;
+            spark.vel *= 0.9;
+            let tail = spark.pos - spark.vel * 0.02;
+            draw_line(spark.pos.x(), spark.pos.y(), tail.x(), tail.y(), 0.06, ORANGE);
+        }
+    }
+
+    /// Shows where the hook would go if it were fired now, and circles whatever it would grab.
+    fn draw_aim(world: &World, car: &Car, hook: &Hook) {
+        if let Hook::Ready { facing } = *hook {
+            let dock = car.dock();
+            // the same sweep the claws make once they're fired, so whatever it shows is what they'd get to
+            let hit = Hook::aim(&world.arena, dock, facing);
+            let end = hit.map_or(dock + facing * Hook::REACH, |hit| hit.pos);
+            draw_line(dock.x(), dock.y(), end.x(), end.y(), 0.04, AIM);
+            if let Some(hit) = hit {
+                // a tick standing off the surface, right where the claws would get to
+                let tick = hit.pos + hit.normal * 0.3;
+                draw_line(hit.pos.x(), hit.pos.y(), tick.x(), tick.y(), 0.04, AIM);
+            }
+            if let Some((pos, radius)) = hit.and_then(|hit| match hit.key {
+                ArenaKey::Can(can) => world.cans.get(can).map(|can| (can.pos, 0.5)),
+                ArenaKey::Anchor(i) => Some(world.map.anchors[i]),
+                _ => None,
+            }) {
+                draw_circle_lines(pos.x(), pos.y(), radius + 0.2, 0.06, AIM);
+            }
+        }
+    }
+
+    /// Draws `world` as it was `alpha` of the way between `prev` and its latest step,
+    /// with the car and hook of a `ghost` faded in underneath if there is one.
+    pub fn draw(&mut self, world: &World, prev: &Snapshot, ghost: Option<&Ghost>, alpha: f32) {
+        let car = world.car.interpolate(&prev.car, alpha);
+        let mut hook = world.hook.interpolate(&prev.hook, alpha);
+        let chain = world.chain.interpolate(&prev.chain, alpha);
+        let mut cans = world.cans.interpolate(&prev.cans, alpha);
+
+        clear_background(world.map.ground.color);
+        set_camera(camera(&car));
+        world.map.draw();
+        if let Some(Ghost { world, prev, .. }) = ghost {
+            let car = world.car.interpolate(&prev.car, alpha);
+            let mut hook = world.hook.interpolate(&prev.hook, alpha);
+            car.draw(self.car_tex, GHOST);
+            hook.draw_hook(car.dock(), &world.clock, GHOST_CHAIN);
+            world.chain.interpolate(&prev.chain, alpha).draw(GHOST_CHAIN);
+        }
+        if let Some(zone) = match &world.mode {
+            Mode::Delivery(delivery) => delivery.target(),
+            _ => None,
+        } {
+            let (pos, radius) = world.map.zones[zone];
+            draw_circle(pos.x(), pos.y(), radius, Color::new(0.0, 0.8, 0.3, 0.35));
+        }
+        Self::draw_aim(world, &car, &hook);
+        car.draw(self.car_tex, WHITE);
+        hook.draw_hook(car.dock(), &world.clock, LIGHTGRAY);
+        cans.draw();
+        chain.draw(LIGHTGRAY);
+        self.draw_sparks();
+
+        #[cfg(feature = "showcollision")]
+        for c in car.colliders().chain(cans.colliders()).chain(hook.colliders()).chain(world.map.triggers()) {
+            outline(&c.shape, RED);
+        }
+
+        set_default_camera();
+        hud(world);
+    }
+}
+
+/// Seconds as minutes, seconds and hundredths, like a stopwatch.
+fn stopwatch(seconds: f64) -> String {
+    format!("{}:{:05.2}", (seconds / 60.0) as u64, seconds % 60.0)
+}
+
+const HUD_SIZE: f32 = 30.0;
+
+/// Lines of text down from the top of the screen, starting `x` across.
+fn hud_lines(x: f32, lines: &[String]) {
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(line, x, 20.0 + HUD_SIZE * (i + 1) as f32, HUD_SIZE, BLACK);
+    }
+}
+
+/// How a round went, in a box in the middle of the screen.
+fn results(title: &str, lines: &[String]) {
+    let (x, y) = (screen_width() / 2.0 - HUD_SIZE * 5.0, screen_height() / 3.0);
+    let height = HUD_SIZE * (lines.len() + 3) as f32;
+    draw_rectangle(x - HUD_SIZE, y - HUD_SIZE * 2.0, HUD_SIZE * 12.0, height, Color::new(1.0, 1.0, 1.0, 0.85));
+    draw_text(title, x, y, HUD_SIZE * 2.0, BLACK);
+    for (i, line) in lines.iter().chain(Some(&"R to go again".to_string())).enumerate() {
+        draw_text(line, x, y + HUD_SIZE * (i + 1) as f32, HUD_SIZE, BLACK);
+    }
+}
+
+/// Lap times, splits and anything else about how the game is going, around the edges of the screen.
+fn hud(world: &World) {
+    let now = world.clock.now();
+    let race = &world.race;
+    let (next, gates) = race.progress();
+    let mut lines = vec![
+        format!("lap {}  {}", race.laps + 1, race.lap_time(now).map_or("-:--.--".to_string(), stopwatch)),
+        format!("checkpoint {}/{}", (next + gates - 1) % gates, gates - 1),
+    ];
+    // each split this lap, and how far up or down it is on the same stretch of the best lap
+    let best_splits = race.best_lap.as_ref().map_or(&[][..], |lap| &lap.splits[..]);
+    lines.extend(race.splits.iter().enumerate().map(|(i, &split)| match best_splits.get(i) {
+        Some(&best) => format!("  {}  {}  {:+.2}", i + 1, stopwatch(split), split - best),
+        None => format!("  {}  {}", i + 1, stopwatch(split)),
+    }));
+    if let Some(lap) = &race.last_lap {
+        lines.push(format!("last {}", stopwatch(lap.time)));
+    }
+    if let Some(lap) = &race.best_lap {
+        lines.push(format!("best {}", stopwatch(lap.time)));
+    }
+    hud_lines(20.0, &lines);
+
+    let right = screen_width() - HUD_SIZE * 7.0;
+    match &world.mode {
+        Mode::Clearing(clearing) => {
+            let (cleared, total) = clearing.cleared();
+            let round = clearing.round();
+            let mut lines = vec![
+                format!("{} pts", round.score()),
+                stopwatch(round.time_left(now)),
+                format!("{}/{} cans", cleared, total),
+            ];
+            if clearing.combo() > 1 {
+                lines.push(format!("combo x{}", clearing.combo()));
+            }
+            hud_lines(right, &lines);
+
+            if let Some(r) = round.results() {
+                results(if r.cleared == r.total { "ALL CLEAR" } else { "TIME UP" }, &[
+                    format!("score {}", r.score),
+                    format!("cans {}/{}", r.cleared, r.total),
+                    format!("time {}", stopwatch(r.time)),
+                    format!("best combo x{}", r.best_combo),
+                ]);
+            }
+        }
+        Mode::Delivery(delivery) => {
+            let round = delivery.round();
+            hud_lines(right, &[
+                format!("{} pts", round.score()),
+                stopwatch(round.time_left(now)),
+                format!("{} delivered", delivery.delivered()),
+            ]);
+
+            if let Some(r) = round.results() {
+                let title = match r.outcome {
+                    Outcome::TimeUp => "TIME UP",
+                    Outcome::AllDelivered => "ALL DELIVERED",
+                    Outcome::NoZones => "NO ZONES",
+                    Outcome::OutOfZones => "OUT OF ZONES",
+                };
+                results(title, &[
+                    format!("score {}", r.score),
+                    format!("delivered {}", r.delivered),
+                    format!("dropped {}", r.drops),
+                ]);
+            }
+        }
+        Mode::Free => if race.wrong_way {
+            draw_text("WRONG WAY", screen_width() / 2.0 - HUD_SIZE * 3.0, screen_height() / 3.0, HUD_SIZE * 2.0, RED);
+        },
+    }
+}
+
+/// Traces the edge of a collision shape.
+#[cfg(feature = "showcollision")]
+fn outline(shape: &super::Shape, color: Color) {
+    let hull = shape.hull();
+    for (a, b) in hull.edges() {
+        if hull.radius > 0.0 {
+            draw_circle_lines(a.x(), a.y(), hull.radius, 0.1, color);
+        }
+        if a != b {
+            let out = super::math::perp_vec((b - a).normalize()) * hull.radius;
+            for &side in &[out, -out] {
+                draw_line(a.x() + side.x(), a.y() + side.y(), b.x() + side.x(), b.y() + side.y(), 0.1, color);
+            }
+        }
+    }
+    if let Some(&last) = hull.corners().last() {
+        if hull.radius > 0.0 {
+            draw_circle_lines(last.x(), last.y(), hull.radius, 0.1, color);
+        }
+    }
+}
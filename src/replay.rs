@@ -0,0 +1,156 @@
+use macroquad::prelude::*;
+use std::io::{self, Read, Write};
+use super::{Input, input::Scripted, mode::Kind};
+
+const MAGIC: &[u8; 4] = b"DNRP";
+/// Bumped whenever the layout of a replay file changes.
+const VERSION: u8 = 3;
+
+/// Every step's Input for one run, enough to simulate that run again exactly.
+/// Runs of identical input are stored once alongside how many steps they were held for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    /// Which Map the run was played on.
+    pub map: String,
+    /// Which Mode the run was played in.
+    pub mode: Kind,
+    pub runs: Vec<(usize, Input)>,
+}
+impl Replay {
+    pub fn new(map: &str, mode: Kind) -> Self {
+        Self {
+            map: map.to_string(),
+            mode,
+            runs: vec![],
+        }
+    }
+
+    /// Notes down the input for one more step.
+    pub fn record(&mut self, input: Input) {
+        match self.runs.last_mut() {
+            Some((steps, last)) if *last == input => *steps += 1,
+            _ => self.runs.push((1, input)),
+        }
+    }
+
+    /// How many steps the run lasted.
+    pub fn steps(&self) -> usize {
+        self.runs.iter().map(|&(steps, _)| steps).sum()
+    }
+
+    /// An InputSource that feeds the recorded inputs back in order.
+    pub fn playback(&self) -> Scripted {
+        Scripted::new(self.runs.clone())
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        Self::read_from(std::fs::File::open(path)?)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        self.write_to(io::BufWriter::new(std::fs::File::create(path)?))
+    }
+
+    pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        w.write_all(&(self.map.len() as u16).to_le_bytes())?;
+        w.write_all(self.map.as_bytes())?;
+        w.write_all(&[self.mode as u8])?;
+        w.write_all(&(self.runs.len() as u32).to_le_bytes())?;
+        for &(steps, Input { throttle, steer, aim, fire, reel }) in &self.runs {
+            w.write_all(&(steps as u32).to_le_bytes())?;
+            w.write_all(&[throttle as u8 | (fire as u8) << 1])?;
+            for f in &[steer, aim.x(), aim.y(), reel] {
+                w.write_all(&f.to_le_bytes())?;
+            }
+        }
+        w.flush()
+    }
+
+    pub fn read_from(mut r: impl Read) -> io::Result<Self> {
+        fn invalid(msg: String) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        }
+        fn bytes<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+            let mut buf = [0; N];
+            r.read_exact(&mut buf)?;
+            Ok(buf)
+        }
+        fn float(r: &mut impl Read) -> io::Result<f32> {
+            bytes(r).map(f32::from_le_bytes)
+        }
+
+        if &bytes::<4>(&mut r)? != MAGIC {
+            return Err(invalid("not a replay file".to_string()));
+        }
+        let [version] = bytes(&mut r)?;
+        if version != VERSION {
+            return Err(invalid(format!("replay is version {}, expected {}", version, VERSION)));
+        }
+
+        let mut map = vec![0; u16::from_le_bytes(bytes(&mut r)?) as usize];
+        r.read_exact(&mut map)?;
+        let map = String::from_utf8(map).map_err(|e| invalid(e.to_string()))?;
+        let mode = match bytes(&mut r)? {
+            [0] => Kind::Free,
+            [1] => Kind::Clearing,
+            [2] => Kind::Delivery,
+            [other] => return Err(invalid(format!("replay was played in mode {}, which doesn't exist", other))),
+        };
+
+        let count = u32::from_le_bytes(bytes(&mut r)?);
+        let runs = (0..count)
+            .map(|_| {
+                let steps = u32::from_le_bytes(bytes(&mut r)?) as usize;
+                let [flags] = bytes(&mut r)?;
+                Ok((steps, Input {
+                    throttle: flags & 1 != 0,
+                    fire: flags & 2 != 0,
+                    steer: float(&mut r)?,
+                    aim: vec2(float(&mut r)?, float(&mut r)?),
+                    reel: float(&mut r)?,
+                }))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { map, mode, runs })
+    }
+}
+
+#[test]
+fn replay_reproduces_run() {
+    use super::{input::{Bot, InputSource}, Map, Mode, World};
+    // played for points, so the replay has to remember that to score the same
+    let mut world = World::new(Map::donut());
+    world.mode = Kind::Clearing.start(&world);
+    let mut replay = Replay::new(world.map.name(), world.mode.kind());
+    let mut bot = Bot::default();
+    for i in 0..60 * 10 {
+        let input = Input { fire: i % 90 == 0, ..bot.input(&world) };
+        replay.record(input);
+        world.step(input);
+    }
+
+    let mut file = vec![];
+    replay.write_to(&mut file).unwrap();
+    let loaded = Replay::read_from(&file[..]).unwrap();
+    assert_eq!(loaded, replay);
+
+    let mut again = World::new(Map::donut());
+    again.mode = loaded.mode.start(&again);
+    let mut playback = loaded.playback();
+    for _ in 0..loaded.steps() {
+        let input = playback.input(&again);
+        again.step(input);
+    }
+    assert_eq!(again.car.pos, world.car.pos);
+    for (a, b) in again.cans.values().zip(world.cans.values()) {
+        assert_eq!(a.pos, b.pos);
+    }
+    let score = |world: &World| match &world.mode {
+        Mode::Clearing(clearing) => clearing.round().score(),
+        _ => panic!("replay wasn't played back for points"),
+    };
+    assert_eq!(score(&again), score(&world));
+}
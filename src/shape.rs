@@ -0,0 +1,320 @@
+use macroquad::prelude::*;
+use super::math::*;
+
+/// The outline of something that can be collided with.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Shape {
+    Circle {
+        pos: Vec2,
+        radius: f32,
+    },
+    /// A line with rounded ends, `radius` thick on either side.
+    Capsule {
+        a: Vec2,
+        b: Vec2,
+        radius: f32,
+    },
+    /// An infinitely thin line.
+    Segment {
+        a: Vec2,
+        b: Vec2,
+    },
+    /// A rectangle, `half_extents` out from its center in either direction, rotated by `angle` radians.
+    OrientedBox {
+        center: Vec2,
+        half_extents: Vec2,
+        angle: f32,
+    },
+}
+impl Shape {
+    /// Every shape is a convex polygon of up to four corners, rounded off by some radius.
+    /// Boiling them down to this lets one routine collide any two shapes.
+    pub fn hull(&self) -> Hull {
+        let z = Vec2::zero();
+        match *self {
+            Shape::Circle { pos, radius } => Hull { corners: [pos, z, z, z], count: 1, radius },
+            Shape::Capsule { a, b, radius } => Hull { corners: [a, b, z, z], count: 2, radius },
+            Shape::Segment { a, b } => Hull { corners: [a, b, z, z], count: 2, radius: 0.0 },
+            Shape::OrientedBox { center, half_extents, angle } => {
+                let x = angle_to_vec(angle) * half_extents.x();
+                let y = perp_vec(angle_to_vec(angle)) * half_extents.y();
+                Hull {
+                    corners: [center + x + y, center - x + y, center - x - y, center + x - y],
+                    count: 4,
+                    radius: 0.0,
+                }
+            }
+        }
+    }
+
+    /// The corners of the smallest axis-aligned box holding the shape, as `(min, max)`.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let hull = self.hull();
+        let corners = hull.corners();
+        let (min, max) = corners.iter().skip(1).fold((corners[0], corners[0]), |(min, max), &c| {
+            (min.min(c), max.max(c))
+        });
+        (min - Vec2::splat(hull.radius), max + Vec2::splat(hull.radius))
+    }
+}
+
+/// A convex polygon of up to four corners, rounded off by `radius`.
+/// One corner is a point, two are a line.
+#[derive(Copy, Clone, Debug)]
+pub struct Hull {
+    corners: [Vec2; 4],
+    count: usize,
+    pub radius: f32,
+}
+impl Hull {
+    pub fn corners(&self) -> &[Vec2] {
+        &self.corners[..self.count]
+    }
+
+    /// Each side of the polygon, as pairs of corners.
+    /// A point has a single side from itself to itself, a line has just the one side.
+    pub fn edges(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let corners = self.corners();
+        let sides = if corners.len() > 2 { corners.len() } else { 1 };
+        (0..sides).map(move |i| (corners[i], corners[(i + 1) % corners.len()]))
+    }
+
+    /// Whether `p` is inside the polygon itself, ignoring the radius.
+    /// Points and lines have no inside.
+    pub fn contains(&self, p: Vec2) -> bool {
+        self.count > 2 && self.edges().all(|(a, b)| cross(b - a, p - a) >= 0.0)
+    }
+
+    /// The axes worth checking for the shallowest way out of an overlap.
+    fn axes(&self) -> impl Iterator<Item = Vec2> + '_ {
+        let line = self.count == 2;
+        self.edges()
+            .filter(|(a, b)| a != b)
+            .flat_map(move |(a, b)| {
+                let along = (b - a).normalize();
+                std::iter::once(perp_vec(along)).chain(if line { Some(along) } else { None })
+            })
+    }
+
+    fn project(&self, axis: Vec2) -> (f32, f32) {
+        self.corners().iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), c| {
+            let d = c.dot(axis);
+            (min.min(d), max.max(d))
+        })
+    }
+}
+
+/// The closest points between two line segments, as `(on the first, on the second)`.
+pub fn closest_points(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> (Vec2, Vec2) {
+    let (d1, d2) = (q1 - p1, q2 - p2);
+    let r = p1 - p2;
+    let (a, e, f) = (d1.dot(d1), d2.dot(d2), d2.dot(r));
+    const EPSILON: f32 = 1e-8;
+
+    let (s, t) = if a <= EPSILON && e <= EPSILON {
+        (0.0, 0.0)
+    } else if a <= EPSILON {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(r);
+        if e <= EPSILON {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1.dot(d2);
+            let denom = a * e - b * b;
+            let s = if denom != 0.0 { ((b * f - c * e) / denom).clamp(0.0, 1.0) } else { 0.0 };
+            let t = (b * s + f) / e;
+            if t < 0.0 {
+                ((-c / a).clamp(0.0, 1.0), 0.0)
+            } else if t > 1.0 {
+                (((b - c) / a).clamp(0.0, 1.0), 1.0)
+            } else {
+                (s, t)
+            }
+        }
+    };
+
+    (p1 + d1 * s, p2 + d2 * t)
+}
+
+/// If the two shapes overlap, the direction to push `a` out of `b` and how far.
+pub fn overlap(a: &Shape, b: &Shape) -> Option<(Vec2, f32)> {
+    // by far the most common case, what with all the cans
+    if let (&Shape::Circle { pos: pa, radius: ra }, &Shape::Circle { pos: pb, radius: rb }) = (a, b) {
+        let delta = pa - pb;
+        let dist = delta.length();
+        let depth = (ra + rb) - dist;
+        return match depth > 0.0 {
+            true if dist > 0.0 => Some((delta / dist, depth)),
+            true => Some((Vec2::unit_x(), depth)),
+            false => None,
+        };
+    }
+
+    let (a, b) = (a.hull(), b.hull());
+    let radii = a.radius + b.radius;
+
+    let cores_overlap = a.corners().iter().any(|&c| b.contains(c))
+        || b.corners().iter().any(|&c| a.contains(c));
+    if !cores_overlap {
+        let (pa, pb) = a.edges()
+            .flat_map(|(p1, q1)| b.edges().map(move |(p2, q2)| closest_points(p1, q1, p2, q2)))
+            .min_by(|(a0, b0), (a1, b1)| {
+                (*a0 - *b0).length_squared().partial_cmp(&(*a1 - *b1).length_squared()).unwrap()
+            })?;
+        let dist = (pa - pb).length();
+        if dist > 1e-6 {
+            return match dist < radii {
+                true => Some(((pa - pb) / dist, radii - dist)),
+                false => None,
+            };
+        }
+    }
+
+    // the polygons themselves are tangled up, so find the shallowest way out of each other
+    a.axes()
+        .chain(b.axes())
+        .map(|axis| {
+            let ((a_min, a_max), (b_min, b_max)) = (a.project(axis), b.project(axis));
+            let (down, up) = (a_max - b_min, b_max - a_min);
+            if down < up {
+                (-axis, down + radii)
+            } else {
+                (axis, up + radii)
+            }
+        })
+        .min_by(|(_, d0), (_, d1)| d0.partial_cmp(d1).unwrap())
+        .or_else(|| Some((Vec2::unit_x(), radii)))
+}
+
+/// Where a ray from `origin` heading along `dir` first meets a circle, as distance along the ray and normal.
+fn ray_circle(origin: Vec2, dir: Vec2, center: Vec2, radius: f32) -> Option<(f32, Vec2)> {
+    let to = origin - center;
+    let b = to.dot(dir);
+    let c = to.length_squared() - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = -b - discriminant.sqrt();
+    if t < 0.0 {
+        return None;
+    }
+    let normal = origin + dir * t - center;
+    Some((t, if radius > 0.0 { normal / radius } else { -dir }))
+}
+
+/// Where a ray from `origin` heading along `dir` first crosses the line from `a` to `b`, as distance along the ray.
+fn ray_segment(origin: Vec2, dir: Vec2, a: Vec2, b: Vec2) -> Option<f32> {
+    let along = b - a;
+    let denom = cross(dir, along);
+    if denom == 0.0 {
+        return None;
+    }
+    let to = a - origin;
+    let t = cross(to, along) / denom;
+    let u = cross(to, dir) / denom;
+    if t >= 0.0 && (0.0..=1.0).contains(&u) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Sweeps a circle of `radius` from `origin` along `dir`, which should be normalized, for up to `max`.
+/// If it would touch `shape` on the way, gives how far it got and the normal of the surface it touched.
+/// A `radius` of zero casts a plain ray.
+pub fn cast(shape: &Shape, origin: Vec2, dir: Vec2, max: f32, radius: f32) -> Option<(f32, Vec2)> {
+    let hull = shape.hull();
+    let r = hull.radius + radius;
+    // already touching before it's gone anywhere
+    let touching = hull.edges().any(|(a, b)| (closest_points(origin, origin, a, b).1 - origin).length() < r);
+    if hull.contains(origin) || touching {
+        return Some((0.0, -dir));
+    }
+
+    // the hull grown by r is every edge as a capsule of r, so whichever capsule the ray reaches first
+    hull.edges()
+        .flat_map(|(a, b)| {
+            let side = if a != b { perp_vec((b - a).normalize()) * r } else { Vec2::zero() };
+            let sides = [side, -side].iter()
+                .filter_map(|&out| Some((ray_segment(origin, dir, a + out, b + out)?, out / r)))
+                .collect::<Vec<_>>();
+            ray_circle(origin, dir, a, r)
+                .into_iter()
+                .chain(ray_circle(origin, dir, b, r))
+                .chain(sides)
+        })
+        .filter(|&(t, _)| t <= max)
+        .min_by(|(t0, _), (t1, _)| t0.partial_cmp(t1).unwrap())
+}
+
+#[test]
+fn circles_overlap_like_circles() {
+    let a = Shape::Circle { pos: vec2(1.5, 0.0), radius: 1.0 };
+    let b = Shape::Circle { pos: vec2(0.0, 0.0), radius: 1.0 };
+    let (normal, depth) = overlap(&a, &b).unwrap();
+    assert!((normal - vec2(1.0, 0.0)).length() < 1e-5);
+    assert!((depth - 0.5).abs() < 1e-5);
+    assert!(overlap(&Shape::Circle { pos: vec2(2.5, 0.0), radius: 1.0 }, &b).is_none());
+}
+
+#[test]
+fn circle_against_box_pushes_out_nearest_side() {
+    let wall = Shape::OrientedBox { center: vec2(0.0, 0.0), half_extents: vec2(5.0, 1.0), angle: 0.0 };
+    // touching the top from outside
+    let (normal, depth) = overlap(&Shape::Circle { pos: vec2(2.0, 1.25), radius: 0.5 }, &wall).unwrap();
+    assert!((normal - vec2(0.0, 1.0)).length() < 1e-5);
+    assert!((depth - 0.25).abs() < 1e-5);
+    // center sunk inside the box, still closest to the top
+    let (normal, depth) = overlap(&Shape::Circle { pos: vec2(2.0, 0.75), radius: 0.5 }, &wall).unwrap();
+    assert!((normal - vec2(0.0, 1.0)).length() < 1e-5);
+    assert!((depth - 0.75).abs() < 1e-5);
+}
+
+#[test]
+fn capsule_against_segment_and_rotated_box() {
+    let capsule = Shape::Capsule { a: vec2(-1.0, 0.4), b: vec2(1.0, 0.4), radius: 0.5 };
+    let floor = Shape::Segment { a: vec2(-5.0, 0.0), b: vec2(5.0, 0.0) };
+    let (normal, depth) = overlap(&capsule, &floor).unwrap();
+    assert!((normal - vec2(0.0, 1.0)).length() < 1e-5);
+    assert!((depth - 0.1).abs() < 1e-5);
+
+    let diamond = Shape::OrientedBox {
+        center: vec2(0.0, -1.0),
+        half_extents: vec2(1.0, 1.0),
+        angle: std::f32::consts::FRAC_PI_4,
+    };
+    let (normal, _) = overlap(&capsule, &diamond).unwrap();
+    assert!(normal.y() > 0.9);
+    assert!(overlap(&Shape::Segment { a: vec2(-5.0, 3.0), b: vec2(5.0, 3.0) }, &diamond).is_none());
+}
+
+#[test]
+fn casts_hit_the_near_side() {
+    let can = Shape::Circle { pos: vec2(5.0, 0.0), radius: 0.5 };
+    let (t, normal) = cast(&can, vec2(0.0, 0.0), vec2(1.0, 0.0), 10.0, 0.0).unwrap();
+    assert!((t - 4.5).abs() < 1e-5);
+    assert!((normal - vec2(-1.0, 0.0)).length() < 1e-5);
+    let (t, _) = cast(&can, vec2(0.0, 0.0), vec2(1.0, 0.0), 10.0, 0.25).unwrap();
+    assert!((t - 4.25).abs() < 1e-5);
+    assert!(cast(&can, vec2(0.0, 0.0), vec2(1.0, 0.0), 4.0, 0.0).is_none());
+    assert!(cast(&can, vec2(0.0, 0.0), vec2(0.0, 1.0), 10.0, 0.0).is_none());
+
+    let wall = Shape::OrientedBox { center: vec2(0.0, 5.0), half_extents: vec2(3.0, 1.0), angle: 0.0 };
+    let (t, normal) = cast(&wall, vec2(1.0, 0.0), vec2(0.0, 1.0), 10.0, 0.5).unwrap();
+    assert!((t - 3.5).abs() < 1e-5);
+    assert!((normal - vec2(0.0, -1.0)).length() < 1e-5);
+}
+
+#[test]
+fn casts_starting_inside_hit_straight_away() {
+    let can = Shape::Circle { pos: vec2(5.0, 0.0), radius: 0.5 };
+    assert_eq!(cast(&can, vec2(5.2, 0.0), vec2(1.0, 0.0), 10.0, 0.0), Some((0.0, vec2(-1.0, 0.0))));
+    // not quite inside, but the swept circle already overlaps it
+    assert_eq!(cast(&can, vec2(4.3, 0.0), vec2(0.0, 1.0), 10.0, 0.25), Some((0.0, vec2(0.0, -1.0))));
+
+    let claws = Shape::Capsule { a: vec2(0.0, 0.0), b: vec2(4.0, 0.0), radius: 0.5 };
+    assert_eq!(cast(&claws, vec2(2.0, 0.3), vec2(0.0, 1.0), 10.0, 0.0), Some((0.0, vec2(0.0, -1.0))));
+    assert_eq!(cast(&claws, vec2(-0.3, 0.0), vec2(1.0, 0.0), 10.0, 0.0), Some((0.0, vec2(-1.0, 0.0))));
+}
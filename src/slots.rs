@@ -0,0 +1,115 @@
+/// Refers to something kept in `Slots`, and keeps referring to only that thing.
+/// Once it's removed, the handle is stale and lookups with it find nothing,
+/// even after its slot is reused for something else.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+    /// Bumped every time whatever's in the slot is removed.
+    generation: u32,
+    value: Option<T>,
+}
+
+/// A Vec that hands out `Handle`s instead of indices,
+/// so removing things doesn't change what the handles to everything else point at.
+/// Goes through its contents in the order of their slots, which is the same from run to run.
+#[derive(Clone)]
+pub struct Slots<T> {
+    slots: Vec<Slot<T>>,
+    /// Empty slots, the most recently emptied last.
+    free: Vec<u32>,
+}
+impl<T> Slots<T> {
+    pub fn new() -> Self {
+        Self { slots: vec![], free: vec![] }
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                Handle { index, generation: slot.generation }
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, value: Some(value) });
+                Handle { index: self.slots.len() as u32 - 1, generation: 0 }
+            }
+        }
+    }
+
+    /// Takes out whatever `handle` refers to, if it's still here.
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        let value = slot.value.take()?;
+        slot.generation += 1;
+        self.free.push(handle.index);
+        Some(value)
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.slots.get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)?
+            .value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        self.slots.get_mut(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)?
+            .value.as_mut()
+    }
+
+    /// Everything still here, along with the handle to it.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            let handle = Handle { index: i as u32, generation: slot.generation };
+            slot.value.as_ref().map(|value| (handle, value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle, &mut T)> + '_ {
+        self.slots.iter_mut().enumerate().filter_map(|(i, slot)| {
+            let handle = Handle { index: i as u32, generation: slot.generation };
+            slot.value.as_mut().map(|value| (handle, value))
+        })
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> + '_ {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+}
+impl<T> std::iter::FromIterator<T> for Slots<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut slots = Slots::new();
+        for value in iter {
+            slots.insert(value);
+        }
+        slots
+    }
+}
+
+#[test]
+fn stale_handles_find_nothing() {
+    let mut slots: Slots<&str> = vec!["a", "b"].into_iter().collect();
+    let handles: Vec<_> = slots.iter().map(|(h, _)| h).collect();
+    assert_eq!(slots.remove(handles[0]), Some("a"));
+    assert_eq!(slots.remove(handles[0]), None);
+
+    // the freed slot gets reused, but the old handle still doesn't see into it
+    let c = slots.insert("c");
+    assert_eq!(slots.get(handles[0]), None);
+    assert_eq!(slots.get(c), Some(&"c"));
+    assert_eq!(slots.get(handles[1]), Some(&"b"));
+    assert_eq!(slots.values().copied().collect::<Vec<_>>(), vec!["c", "b"]);
+}
@@ -0,0 +1,76 @@
+use macroquad::prelude::*;
+
+/// How a patch of ground treats whatever's driving or sliding across it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Surface {
+    /// How quickly the car's motion comes around to where it's pointing, 1 being tarmac.
+    pub grip: f32,
+    /// How much of its speed the car loses each step it's going faster than the throttle pushes it,
+    /// and how far short of full speed the throttle falls for it. Below 0, the throttle pushes it past full speed instead.
+    pub drag: f32,
+    /// How sharply the car turns, 1 being tarmac.
+    pub steer: f32,
+    /// How much of their speed cans lose each step.
+    pub can_drag: f32,
+    pub color: Color,
+}
+impl Surface {
+    pub const ASPHALT: Surface = Surface {
+        grip: 1.0,
+        drag: 0.04,
+        steer: 1.0,
+        can_drag: 0.04,
+        color: GRAY,
+    };
+    pub const GRASS: Surface = Surface {
+        grip: 0.6,
+        drag: 0.08,
+        steer: 0.8,
+        can_drag: 0.1,
+        color: Color::new(0.88, 0.94, 0.84, 1.0),
+    };
+    pub const ICE: Surface = Surface {
+        grip: 0.15,
+        drag: 0.01,
+        steer: 0.5,
+        can_drag: 0.005,
+        color: Color::new(0.8, 0.93, 1.0, 1.0),
+    };
+    pub const OIL: Surface = Surface {
+        grip: 0.05,
+        drag: 0.02,
+        steer: 0.2,
+        can_drag: 0.01,
+        color: Color::new(0.15, 0.12, 0.2, 1.0),
+    };
+    pub const MUD: Surface = Surface {
+        grip: 0.8,
+        drag: 0.25,
+        steer: 0.7,
+        can_drag: 0.3,
+        color: Color::new(0.45, 0.33, 0.2, 1.0),
+    };
+    pub const BOOST: Surface = Surface {
+        grip: 1.0,
+        drag: -0.4,
+        steer: 1.0,
+        can_drag: 0.04,
+        color: ORANGE,
+    };
+
+    /// The surfaces every map can use without defining them itself.
+    pub const BUILT_IN: [(&'static str, Surface); 6] = [
+        ("asphalt", Surface::ASPHALT),
+        ("grass", Surface::GRASS),
+        ("ice", Surface::ICE),
+        ("oil", Surface::OIL),
+        ("mud", Surface::MUD),
+        ("boost", Surface::BOOST),
+    ];
+
+    /// The color the side of something made of this would be, in the shade.
+    pub fn shade(&self) -> Color {
+        let Color { r, g, b, a } = self.color;
+        Color::new(r * 0.6, g * 0.6, b * 0.6, a)
+    }
+}
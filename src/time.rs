@@ -0,0 +1,64 @@
+/// How many seconds the simulation advances every step, regardless of how fast the screen refreshes.
+/// Speeds, frictions and impulses are all tuned per step, and they were tuned at 60hz.
+pub const TIMESTEP: f32 = 1.0 / 60.0;
+
+/// Frames longer than this (dragging the window, sitting at a breakpoint) are cut short,
+/// so we never try to catch up on seconds of simulation at once.
+const MAX_FRAME_TIME: f32 = 0.25;
+
+/// Turns variable frame times into a whole number of fixed simulation steps,
+/// carrying whatever time is left over into the next frame.
+pub struct FixedTimestep {
+    accumulator: f32,
+}
+impl FixedTimestep {
+    pub fn new() -> Self {
+        Self { accumulator: 0.0 }
+    }
+
+    /// Feed in how long the last frame took, get back how many steps to simulate.
+    pub fn advance(&mut self, frame_time: f32) -> usize {
+        self.accumulator += frame_time.min(MAX_FRAME_TIME);
+        let steps = (self.accumulator / TIMESTEP) as usize;
+        self.accumulator -= steps as f32 * TIMESTEP;
+        steps
+    }
+
+    /// How far we are between the last step and the next one, from 0 to 1.
+    /// Rendering blends the previous and current state by this much.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / TIMESTEP
+    }
+}
+
+#[test]
+fn timestep_carries_leftover_time() {
+    let mut t = FixedTimestep::new();
+    assert_eq!(t.advance(TIMESTEP * 0.5), 0);
+    assert_eq!(t.advance(TIMESTEP * 0.75), 1);
+    assert!((t.alpha() - 0.25).abs() < 0.001);
+    assert!(t.advance(10.0) <= (MAX_FRAME_TIME / TIMESTEP) as usize + 1);
+}
+
+/// Counts simulation steps.
+/// Gameplay reads the time from here instead of the wall clock, so pausing,
+/// replays and tests all see time pass exactly as fast as the simulation does.
+#[derive(Copy, Clone, Default)]
+pub struct SimClock {
+    ticks: u64,
+}
+impl SimClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called once at the end of every simulation step.
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    /// Seconds of simulation that have passed.
+    pub fn now(&self) -> f64 {
+        self.ticks as f64 * TIMESTEP as f64
+    }
+}
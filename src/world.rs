@@ -0,0 +1,539 @@
+use macroquad::prelude::Vec2;
+use super::{ArenaKey, Can, Cantainer, Car, Chain, CircleArena, Collision, Handle, Hook, Input, Map, Mode, Race, SimClock, Winch, circle::{self, Body, TriggerEvent}};
+
+/// Something noteworthy that happened during a step, for game modes and effects to react to.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Event {
+    Trigger(TriggerEvent),
+    /// The car grinding along a wall at `pos`, going `speed` along it.
+    Scrape {
+        pos: Vec2,
+        speed: f32,
+    },
+    /// The hook latching onto a can.
+    Grab {
+        can: Handle,
+    },
+    /// The hook letting go of a can.
+    Release {
+        can: Handle,
+    },
+}
+
+/// Everything that gets simulated, and nothing that draws it.
+/// Stepping a World never touches the window, so it runs just as well in a test.
+#[derive(Clone)]
+pub struct World {
+    pub map: Map,
+    pub car: Car,
+    pub hook: Hook,
+    pub winch: Winch,
+    pub chain: Chain,
+    pub cans: Cantainer,
+    pub arena: CircleArena,
+    pub clock: SimClock,
+    pub race: Race,
+    pub mode: Mode,
+    /// What happened during the latest step.
+    pub events: Vec<Event>,
+    collisions: Vec<Collision>,
+}
+impl World {
+    pub fn new(map: Map) -> Self {
+        let (pos, dir) = map.car_spawn();
+        let car = Car {
+            pos,
+            dir,
+            ..Car::new()
+        };
+        Self {
+            chain: Chain::new(car.dock()),
+            car,
+            hook: Hook::new(),
+            winch: Winch::default(),
+            cans: Cantainer::new(map.can_spots().map(Can::new).collect()),
+            arena: CircleArena::new(),
+            clock: SimClock::new(),
+            race: Race::new(&map),
+            mode: Mode::Free,
+            events: vec![],
+            collisions: vec![],
+            map,
+        }
+    }
+
+    /// Whether every can has been knocked off the road.
+    pub fn cleared(&self) -> bool {
+        self.cans.values().all(|can| !self.map.on_road(can.pos))
+    }
+
+    /// The body behind `key`, if it's something collisions should push around.
+    fn body(&self, key: ArenaKey) -> Option<Body> {
+        match key {
+            ArenaKey::Car => Some(Body { vel: self.car.velocity(), material: Car::MATERIAL }),
+            ArenaKey::Can(can) => self.cans.get(can).map(|can| Body { vel: can.vel, material: can.material }),
+            ArenaKey::Wall(i) => Some(Body { vel: Vec2::zero(), material: self.map.walls[i].material() }),
+            ArenaKey::Anchor(_) => Some(Body { vel: Vec2::zero(), material: Map::ANCHOR_MATERIAL }),
+            ArenaKey::Hook | ArenaKey::Trigger(_) | ArenaKey::Zone(_) => None,
+        }
+    }
+
+    fn bump(&mut self, key: ArenaKey, impulse: Vec2, push: Vec2) {
+        match key {
+            ArenaKey::Car => self.car.bump(impulse, push),
+            ArenaKey::Can(can) => if let Some(can) = self.cans.get_mut(can) {
+                can.bump(impulse, push)
+            },
+            ArenaKey::Hook | ArenaKey::Trigger(_) | ArenaKey::Wall(_) | ArenaKey::Zone(_) | ArenaKey::Anchor(_) => {},
+        }
+    }
+
+    fn collision(&mut self, collision: Collision) {
+        let Self { map, car, hook, cans, events, .. } = self;
+        match collision.members {
+            [ArenaKey::Hook, _] | [_, ArenaKey::Hook] => {
+                for Collision { members, normal, .. } in collision.both_ways().iter().cloned() {
+                    match members {
+                        [ArenaKey::Hook, other] => events.extend(hook.grab(car.dock(), other, cans, map)),
+                        [ArenaKey::Can(handle), ArenaKey::Hook] => match cans.get_mut(handle) {
+                            Some(can) if can.vel.length() < 0.5 => can.knockback(normal * 0.1),
+                            _ => {},
+                        },
+                        _ => {},
+                    }
+                }
+            }
+            _ if collision.sensor => {},
+            [a, b] => {
+                if let (Some(body_a), Some(body_b)) = (self.body(a), self.body(b)) {
+                    if let Some(r) = circle::resolve(&collision, [body_a, body_b]) {
+                        self.bump(a, r.impulses[0], r.pushes[0]);
+                        self.bump(b, r.impulses[1], r.pushes[1]);
+                    }
+                }
+                self.scrape(collision);
+            }
+        }
+    }
+
+    /// Notes down the car grinding along a wall, if that's what `collision` is.
+    fn scrape(&mut self, collision: Collision) {
+        /// Slower than this along the wall and it's more of a nudge than a scrape.
+        const MIN_SPEED: f32 = 0.02;
+        for Collision { members, normal, .. } in collision.both_ways().iter().cloned() {
+            if let [ArenaKey::Car, ArenaKey::Wall(_)] = members {
+                let vel = self.car.velocity();
+                let speed = (vel - normal * vel.dot(normal)).length();
+                if speed > MIN_SPEED {
+                    // the car's about a meter wide, so its side is about half that from its middle
+                    let pos = self.car.pos - normal * 0.5;
+                    self.events.push(Event::Scrape { pos, speed });
+                }
+            }
+        }
+    }
+
+    /// Advances the simulation by one fixed timestep.
+    pub fn step(&mut self, input: Input) {
+        let Self { map, car, hook, winch, chain, cans, arena, clock, events, .. } = self;
+        events.clear();
+
+        match *hook {
+            Hook::Ready { .. } => hook.face(car.dock(), input.aim),
+            Hook::Launched { .. } | Hook::Retracting { .. } => {
+                if let Some(hit) = hook.fly(car.dock(), clock, arena) {
+                    events.extend(hook.grab(car.dock(), hit.key, cans, map));
+                }
+            }
+            Hook::Anchored { .. } | Hook::Locked { .. } => {},
+        }
+        car.controls(&input, clock, map.surface(car.pos));
+        // the chain pulls against wherever the car drove to, so it has the last say in where the car ends up
+        match *hook {
+            Hook::Anchored { .. } => hook.swing(car, chain, map, input.reel, winch),
+            // whatever the hook was holding is gone, so there's nothing left to hold onto
+            Hook::Locked { can, .. } => match cans.get_mut(can) {
+                Some(can) => hook.drag(car, can, chain, map, input.reel, winch),
+                None => hook.retract(clock),
+            },
+            _ => {},
+        }
+        chain.follow(car.dock(), hook);
+        for can in cans.values_mut() {
+            can.slide(1.0 - map.surface(can.pos).can_drag)
+        }
+        if input.fire {
+            match *hook {
+                Hook::Ready { .. } => hook.launch(car.dock()),
+                Hook::Locked { can: handle, .. } => match cans.get_mut(handle) {
+                    Some(can) => {
+                        hook.release(can, clock);
+                        events.push(Event::Release { can: handle });
+                    }
+                    None => hook.retract(clock),
+                },
+                Hook::Anchored { .. } => hook.retract(clock),
+                _ => {},
+            }
+        }
+
+        arena.collide(
+            car.colliders()
+                .chain(cans.colliders())
+                .chain(hook.colliders())
+                .chain(map.triggers())
+                .chain(map.wall_colliders())
+                .chain(map.zone_colliders())
+                .chain(map.anchor_colliders())
+        );
+        events.extend(arena.triggered().map(Event::Trigger));
+        let mut collisions = std::mem::take(&mut self.collisions);
+        collisions.extend(self.arena.collided());
+        for collision in collisions.drain(..) {
+            self.collision(collision);
+        }
+        self.collisions = collisions;
+        self.race.update(&self.events, self.clock.now(), &self.car, &self.map);
+        // the mode gets the whole World to look at, and it isn't in there while it looks
+        let mut mode = std::mem::replace(&mut self.mode, Mode::Free);
+        mode.update(self);
+        self.mode = mode;
+
+        self.clock.tick();
+    }
+}
+
+#[test]
+fn throttle_moves_the_car() {
+    let mut world = World::new(Map::donut());
+    let spawn = world.car.pos;
+    for _ in 0..120 {
+        world.step(Input { throttle: true, ..Default::default() });
+    }
+    assert!((world.car.pos - spawn).length() > 1.0);
+}
+
+#[test]
+fn knocks_carry_the_car_along() {
+    let mut world = World::new(Map::donut());
+    let spawn = world.car.pos;
+    let knock = world.car.dir * 0.1;
+    world.car.bump(knock, Vec2::zero());
+    for _ in 0..10 {
+        world.step(Input::default());
+    }
+    // a single step's worth of the knock would only get it a tenth of the way there
+    assert!((world.car.pos - spawn).dot(knock.normalize()) > 0.5);
+}
+
+/// Aims the hook at the can, fires it, and gives it time to get there.
+#[cfg(test)]
+fn fire_at(world: &mut World, handle: Handle) {
+    let aim = Input { aim: world.cans.get(handle).unwrap().pos, ..Default::default() };
+    for _ in 0..60 {
+        world.step(aim);
+    }
+    world.step(Input { fire: true, ..aim });
+    for _ in 0..30 {
+        world.step(aim);
+    }
+}
+
+/// Puts a can down at `pos` with the hook already latched onto it, the chain pulled straight from the car.
+#[cfg(test)]
+pub fn hooked(world: &mut World, pos: Vec2) -> Handle {
+    let dock = world.car.dock();
+    let handle = world.cans.insert(Can::new(pos));
+    world.hook = Hook::Launched { pos: dock, vel: 0.0, facing: (pos - dock).normalize() };
+    world.hook.grab(dock, ArenaKey::Can(handle), &mut world.cans, &world.map);
+    match &mut world.hook {
+        Hook::Locked { end, chain_length, .. } => {
+            *chain_length = (*end - dock).length();
+            world.chain.lay(dock, *end);
+        }
+        other => panic!("hook didn't grab the can: {:?}", other),
+    }
+    handle
+}
+
+#[test]
+fn hook_grabs_can_it_is_fired_at() {
+    let mut world = World::new(Map::donut());
+    let handle = world.cans.iter().map(|(h, _)| h).nth(5).unwrap();
+    fire_at(&mut world, handle);
+    assert!(matches!(world.hook, Hook::Locked { can, .. } if can == handle));
+}
+
+#[test]
+fn hook_cannot_fly_through_a_can() {
+    use macroquad::prelude::vec2;
+    let mut world = World::new(Map::donut());
+    world.car.pos = vec2(0.0, 0.0);
+    world.cans = Cantainer::new(vec![Can::new(vec2(5.0, 3.0))]);
+    world.step(Input::default());
+    // fast enough to land well past the can in a single step
+    world.hook = Hook::Launched { pos: vec2(0.0, 3.0), vel: 12.0, facing: vec2(1.0, 0.0) };
+    world.step(Input::default());
+    assert!(matches!(world.hook, Hook::Locked { .. }));
+}
+
+#[test]
+fn walls_stop_the_hook_reaching_cans_behind_them() {
+    use super::map::Wall;
+    use circle::RayHit;
+    use macroquad::prelude::vec2;
+    let mut world = World::new(Map::donut());
+    world.map.walls = vec![Wall { points: vec![vec2(3.0, -3.0), vec2(3.0, 3.0)], closed: false, restitution: 0.5 }];
+    world.car.pos = vec2(0.0, 0.0);
+    world.car.dir = vec2(1.0, 0.0);
+    world.cans = Cantainer::new(vec![Can::new(vec2(4.0, 0.0))]);
+    world.step(Input::default());
+    assert!(matches!(Hook::aim(&world.arena, world.car.dock(), vec2(1.0, 0.0)), Some(RayHit { key: ArenaKey::Wall(_), .. })));
+    world.hook = Hook::Launched { pos: world.car.dock(), vel: 12.0, facing: vec2(1.0, 0.0) };
+    world.step(Input::default());
+    assert!(matches!(world.hook, Hook::Retracting { .. }), "{:?}", world.hook);
+}
+
+#[test]
+fn plowing_into_cans_slows_the_car() {
+    use macroquad::prelude::vec2;
+    let gas = Input { throttle: true, ..Default::default() };
+    let run = |cans: Vec<Can>| {
+        let mut world = World::new(Map::donut());
+        world.car.pos = vec2(0.0, 0.0);
+        world.cans = Cantainer::new(cans);
+        for _ in 0..180 {
+            world.step(gas);
+        }
+        world
+    };
+
+    let open_road = run(vec![]);
+    let wall: Vec<Can> = (0..6)
+        .flat_map(|y| (0..3).map(move |x| Can::new(vec2(3.0 + x as f32, y as f32 - 2.5))))
+        .collect();
+    let blocked = run(wall);
+
+    assert!(blocked.car.pos.x() < open_road.car.pos.x());
+    assert!(blocked.cans.values().any(|can| can.vel.length() > 0.0 || can.pos.x() > 5.0));
+}
+
+#[test]
+fn heavy_cans_hold_the_car_back() {
+    use macroquad::prelude::vec2;
+    let tow = |mass: f32| {
+        let mut world = World::new(Map::donut());
+        world.car.pos = vec2(0.0, 0.0);
+        world.cans = Cantainer::new(vec![]);
+        let handle = hooked(&mut world, vec2(-4.0, 0.0));
+        world.cans.get_mut(handle).unwrap().material.mass = mass;
+        for _ in 0..180 {
+            world.step(Input { throttle: true, ..Default::default() });
+        }
+        world
+    };
+
+    let light = tow(1.0);
+    let heavy = tow(20.0);
+    assert!(heavy.car.pos.x() < light.car.pos.x(), "{} isn't short of {}", heavy.car.pos.x(), light.car.pos.x());
+    // something that won't budge at all keeps the car on a leash
+    let fixed = tow(f32::INFINITY);
+    match &fixed.hook {
+        Hook::Locked { end, chain_length, .. } => assert!((fixed.car.dock() - *end).length() <= chain_length + 1e-3),
+        other => panic!("hook let go: {:?}", other),
+    }
+    assert!(fixed.cans.values().all(|can| (can.pos - vec2(-4.0, 0.0)).length() < 0.01));
+}
+
+#[test]
+fn towed_cans_keep_going_when_the_car_stops() {
+    use macroquad::prelude::vec2;
+    let mut world = World::new(Map::donut());
+    world.car.pos = vec2(0.0, 0.0);
+    world.cans = Cantainer::new(vec![]);
+    let handle = hooked(&mut world, vec2(-4.0, 0.0));
+    for _ in 0..120 {
+        world.step(Input { throttle: true, ..Default::default() });
+    }
+
+    // stopped dead, the chain goes slack and the can carries on after the car by itself
+    world.car.speed = 0.0;
+    world.car.vel = Vec2::zero();
+    let was = world.cans.get(handle).unwrap().pos;
+    world.step(Input::default());
+    let can = world.cans.get(handle).unwrap();
+    assert!(can.vel.x() > 0.0 && can.pos.x() > was.x(), "the can stopped along with the car at {:?}", can.pos);
+    assert!(matches!(world.hook, Hook::Locked { .. }), "hook let go: {:?}", world.hook);
+}
+
+#[test]
+fn winch_reels_cans_in_and_lets_them_out() {
+    use macroquad::prelude::vec2;
+    let mut world = World::new(Map::donut());
+    world.car.pos = vec2(0.0, 0.0);
+    world.cans = Cantainer::new(vec![]);
+    let handle = hooked(&mut world, vec2(-6.0, 0.0));
+    let chain_length = |world: &World| match world.hook {
+        Hook::Locked { chain_length, .. } => chain_length,
+        _ => panic!("hook let go"),
+    };
+
+    // wind it all the way in, and the can ends up against the bumper
+    for _ in 0..180 {
+        world.step(Input { reel: -1.0, ..Default::default() });
+    }
+    assert_eq!(chain_length(&world), world.winch.min_length);
+    let can = world.cans.get(handle).unwrap().pos;
+    assert!((can - world.car.pos).length() < 2.0, "can is still out at {:?}", can);
+
+    // letting it out gives it slack, which leaves the can where it is
+    for _ in 0..180 {
+        world.step(Input { reel: 1.0, ..Default::default() });
+    }
+    assert_eq!(chain_length(&world), world.winch.max_length);
+    assert!((world.cans.get(handle).unwrap().pos - can).length() < 0.1);
+}
+
+#[test]
+fn anchored_hook_swings_the_car_around_the_post() {
+    use super::math::cross;
+    use macroquad::prelude::vec2;
+    let mut world = World::new(Map::donut());
+    world.map.anchors = vec![(vec2(0.0, 0.0), 0.4)];
+    world.cans = Cantainer::new(vec![]);
+    world.car.pos = vec2(0.0, -4.0);
+    world.step(Input::default());
+    let dock = world.car.dock();
+    world.hook = Hook::Launched { pos: dock, vel: 1.25, facing: -dock.normalize() };
+    for _ in 0..20 {
+        world.step(Input::default());
+    }
+    assert!(matches!(world.hook, Hook::Anchored { .. }));
+
+    // drive past the post, and round it goes
+    let mut swung = 0.0;
+    for _ in 0..240 {
+        let was = world.car.pos;
+        world.step(Input { throttle: true, ..Default::default() });
+        swung += cross(was, world.car.pos).atan2(was.dot(world.car.pos));
+        match &world.hook {
+            Hook::Anchored { end, chain_length, .. } => assert!((world.car.dock() - *end).length() <= chain_length + 1e-3),
+            other => panic!("hook let go of the post: {:?}", other),
+        }
+    }
+    assert!(swung > std::f32::consts::PI, "only swung {} radians around", swung);
+    assert!(world.car.speed > 0.1, "car stalled at {}", world.car.speed);
+    // and still pointing the way it's going
+    assert!(world.car.dir.dot(world.car.vel) > 0.99);
+}
+
+#[test]
+fn grass_slows_the_car() {
+    use macroquad::prelude::vec2;
+    let drive_from = |pos| {
+        let mut world = World::new(Map::donut());
+        world.car.pos = pos;
+        for _ in 0..120 {
+            world.step(Input { throttle: true, ..Default::default() });
+        }
+        (world.car.pos - pos).length()
+    };
+    // the middle of the donut is all grass
+    assert!(drive_from(vec2(-5.0, 0.0)) < drive_from(vec2(-5.0, 30.0)));
+}
+
+#[test]
+fn coasting_cars_slow_down_by_the_ground_under_them() {
+    use super::Surface;
+    let coast = |road: Surface| {
+        let mut world = World::new(Map::donut());
+        world.map.road = road;
+        world.car.vel = world.car.dir;
+        world.car.speed = 0.15;
+        let from = world.car.pos;
+        for _ in 0..60 {
+            world.step(Input::default());
+        }
+        (world.car.pos - from).length()
+    };
+    assert!(coast(Surface::MUD) < coast(Surface::ASPHALT));
+    assert!(coast(Surface::ASPHALT) < coast(Surface::ICE));
+}
+
+#[test]
+fn driving_over_the_start_line_triggers_it() {
+    use circle::Overlap;
+    let mut world = World::new(Map::donut());
+    let mut overlaps = vec![];
+    for _ in 0..240 {
+        world.step(Input { throttle: true, ..Default::default() });
+        for event in &world.events {
+            if let Event::Trigger(TriggerEvent { trigger: ArenaKey::Trigger(Map::START_LINE), other: ArenaKey::Car, overlap }) = event {
+                overlaps.push(*overlap);
+            }
+        }
+    }
+    assert_eq!(overlaps.first(), Some(&Overlap::Enter));
+    assert_eq!(overlaps.last(), Some(&Overlap::Exit));
+    assert!(overlaps[1..overlaps.len() - 1].iter().all(|&o| o == Overlap::Stay));
+}
+
+#[test]
+fn walls_keep_cans_on_the_road() {
+    use macroquad::prelude::vec2;
+    let mut world = World::new(Map::donut().with_ring_walls(0.8));
+    world.cans = Cantainer::new(vec![Can { vel: vec2(0.0, 1.0), ..Can::new(vec2(0.0, -30.0)) }]);
+    for _ in 0..20 {
+        world.step(Input::default());
+        let can = world.cans.values().next().unwrap();
+        assert!(world.map.on_road(can.pos), "can escaped to {:?}", can.pos);
+    }
+    assert!(world.cans.values().next().unwrap().vel.y() < 0.0, "can should have bounced back");
+}
+
+#[test]
+fn hook_lets_go_of_a_can_that_is_gone() {
+    let mut world = World::new(Map::donut());
+    let handle = world.cans.iter().map(|(h, _)| h).nth(5).unwrap();
+    fire_at(&mut world, handle);
+    world.cans.remove(handle);
+    world.step(Input::default());
+    assert!(matches!(world.hook, Hook::Retracting { .. }));
+}
+
+#[test]
+fn bot_finishes_a_lap() {
+    use super::{InputSource, input::Bot};
+    let mut world = World::new(Map::donut());
+    world.cans = Cantainer::new(vec![]);
+    let mut bot = Bot::default();
+    for _ in 0..60 * 30 {
+        let input = bot.input(&world);
+        world.step(input);
+        assert!(!world.race.wrong_way);
+    }
+    let lap = world.race.last_lap.expect("bot should have gone all the way around");
+    assert_eq!(lap.splits.len(), world.map.gate_count());
+    assert!((lap.splits.iter().sum::<f64>() - lap.time).abs() < 1e-6);
+}
+
+#[test]
+fn bot_drives_straight_through_the_figure_eight_crossing() {
+    use super::{InputSource, input::Bot};
+    let mut world = World::new(Map::parse(include_str!("../maps/figure-eight.map")).unwrap());
+    world.cans = Cantainer::new(vec![]);
+    let mut bot = Bot::default();
+    let (mut passes, mut crossing) = (0, false);
+    for _ in 0..60 * 40 {
+        let input = bot.input(&world);
+        world.step(input);
+        // where the two loops cross, the road runs two ways at once, and the car's on whichever it was already on
+        assert!(!world.race.wrong_way, "wrong way at {:?}", world.car.pos);
+        let was = std::mem::replace(&mut crossing, world.car.pos.length() < 2.0);
+        if crossing && !was {
+            passes += 1;
+        }
+    }
+    // once along each loop's way into it
+    assert!(passes >= 2, "only went through the crossing {} times", passes);
+}